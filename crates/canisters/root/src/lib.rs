@@ -14,6 +14,7 @@ use icu::{
 //
 
 icu_start_root!();
+icu_inspect_message!();
 
 const fn icu_setup() {}
 
@@ -48,4 +49,5 @@ async fn get_icp_rate() -> Result<f64, Error> {
 }
 
 // end
+
 export_candid!();
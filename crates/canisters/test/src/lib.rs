@@ -19,7 +19,7 @@ async fn icu_install(_: Option<Vec<u8>>) {}
 async fn icu_upgrade() {}
 
 // create_test
-#[update]
+#[icu::update(capability = "create", auth(any([controller(), parent()])))]
 async fn create_test() -> Result<CreateCanisterResponse, Error> {
     create_canister_request::<()>(&TEST, None).await
 }
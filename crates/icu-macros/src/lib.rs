@@ -0,0 +1,412 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{ToTokens, format_ident, quote};
+use syn::{
+    Error, Expr, ExprLit, ItemFn, Lit, LitStr, Meta, Token, parse::Parser, parse_macro_input,
+    punctuated::Punctuated,
+};
+
+///
+/// update
+///
+/// #[icu::update(guard = "update", auth_any(is_parent, is_role("admin")))]
+///
+/// guard      : "update" (default), "query" or "none"
+/// capability : guard with the mode of a capability from [app] instead of the app mode
+/// auth_any   : the caller has to pass one of the rules
+/// auth_all   : the caller has to pass every rule
+/// auth       : a single auth expression, ie. auth(any([controller(), not(anonymous())]))
+///
+/// anything else is passed straight through to ic_cdk::update
+///
+#[proc_macro_attribute]
+pub fn update(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemFn);
+
+    expand(Method::Update, attr.into(), item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+///
+/// query
+///
+/// same as update, but the guard defaults to "query"
+///
+#[proc_macro_attribute]
+pub fn query(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemFn);
+
+    expand(Method::Query, attr.into(), item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+///
+/// Method
+///
+
+#[derive(Clone, Copy)]
+enum Method {
+    Update,
+    Query,
+}
+
+impl Method {
+    const fn default_guard(self) -> Guard {
+        match self {
+            Self::Update => Guard::Update,
+            Self::Query => Guard::Query,
+        }
+    }
+}
+
+///
+/// Guard
+///
+
+#[derive(Clone, Copy)]
+enum Guard {
+    Update,
+    Query,
+    None,
+}
+
+///
+/// Args
+///
+
+struct Args {
+    guard: Guard,
+    capability: Option<LitStr>,
+    auth_any: Vec<Expr>,
+    auth_all: Vec<Expr>,
+    auth: Option<Expr>,
+    passthrough: Vec<Meta>,
+}
+
+impl Args {
+    fn parse(method: Method, attr: TokenStream2) -> Result<Self, Error> {
+        let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(attr)?;
+
+        let mut args = Self {
+            guard: method.default_guard(),
+            capability: None,
+            auth_any: Vec::new(),
+            auth_all: Vec::new(),
+            auth: None,
+            passthrough: Vec::new(),
+        };
+
+        for meta in metas {
+            if meta.path().is_ident("guard") {
+                args.guard = parse_guard(&meta)?;
+            } else if meta.path().is_ident("capability") {
+                args.capability = Some(parse_str(&meta, "capability")?);
+            } else if meta.path().is_ident("auth_any") {
+                args.auth_any.extend(parse_rules(&meta)?);
+            } else if meta.path().is_ident("auth_all") {
+                args.auth_all.extend(parse_rules(&meta)?);
+            } else if meta.path().is_ident("auth") {
+                if args.auth.is_some() {
+                    return Err(Error::new_spanned(meta, "auth can only be set once"));
+                }
+                args.auth = Some(meta.require_list()?.parse_args()?);
+            } else {
+                args.passthrough.push(meta);
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+// parse_str
+fn parse_str(meta: &Meta, name: &str) -> Result<LitStr, Error> {
+    let value = &meta.require_name_value()?.value;
+
+    match value {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) => Ok(s.clone()),
+        _ => Err(Error::new_spanned(
+            value,
+            format!("{name} must be a string"),
+        )),
+    }
+}
+
+// parse_guard
+fn parse_guard(meta: &Meta) -> Result<Guard, Error> {
+    let s = parse_str(meta, "guard")?;
+
+    match s.value().as_str() {
+        "update" => Ok(Guard::Update),
+        "query" => Ok(Guard::Query),
+        "none" => Ok(Guard::None),
+        _ => Err(Error::new_spanned(
+            &s,
+            "guard must be \"update\", \"query\" or \"none\"",
+        )),
+    }
+}
+
+// parse_rules
+fn parse_rules(meta: &Meta) -> Result<Vec<Expr>, Error> {
+    let list = meta.require_list()?;
+    let rules = list.parse_args_with(Punctuated::<Expr, Token![,]>::parse_terminated)?;
+
+    if rules.is_empty() {
+        return Err(Error::new_spanned(
+            list,
+            "at least one auth rule is required",
+        ));
+    }
+
+    Ok(rules.into_iter().collect())
+}
+
+// expand
+fn expand(method: Method, attr: TokenStream2, mut item: ItemFn) -> Result<TokenStream2, Error> {
+    let args = Args::parse(method, attr)?;
+    let has_auth = !args.auth_any.is_empty() || !args.auth_all.is_empty() || args.auth.is_some();
+
+    if has_auth && item.sig.asyncness.is_none() {
+        return Err(Error::new_spanned(
+            &item.sig,
+            "auth rules are async, the endpoint has to be an async fn",
+        ));
+    }
+
+    // cdk attribute
+    let mut cdk_args: Vec<TokenStream2> = args
+        .passthrough
+        .iter()
+        .map(ToTokens::to_token_stream)
+        .collect();
+    // a capability gets its own guard function, the cdk guard can't take arguments
+    let mut capability_guard = None;
+    match (&args.capability, args.guard) {
+        (None, Guard::Update) => cdk_args.push(quote!(guard = "::icu::guard::guard_update")),
+        (None, Guard::Query) => cdk_args.push(quote!(guard = "::icu::guard::guard_query")),
+        (None, Guard::None) => {}
+        (Some(capability), Guard::None) => {
+            return Err(Error::new_spanned(
+                capability,
+                "capability needs an update or query guard",
+            ));
+        }
+        (Some(capability), guard) => {
+            let ident = format_ident!("__icu_guard_{}", item.sig.ident);
+            let guard_fn = match guard {
+                Guard::Query => quote!(::icu::guard::guard_query_capability),
+                _ => quote!(::icu::guard::guard_update_capability),
+            };
+            let name = ident.to_string();
+
+            cdk_args.push(quote!(guard = #name));
+            capability_guard = Some(quote! {
+                #[allow(non_snake_case)]
+                fn #ident() -> Result<(), String> {
+                    #guard_fn(#capability)
+                }
+            });
+        }
+    }
+    let cdk_attr = match method {
+        Method::Update => quote!(#[::icu::cdk::update(#(#cdk_args),*)]),
+        Method::Query => quote!(#[::icu::cdk::query(#(#cdk_args),*)]),
+    };
+
+    // auth, the ? hands the error back through the endpoint's Result
+    // and the function name is what ends up in the audit log
+    if has_auth {
+        let method = item.sig.ident.to_string();
+        let mut checks = Vec::new();
+        if !args.auth_all.is_empty() {
+            let rules = &args.auth_all;
+            checks.push(quote! {
+                ::icu::auth::require_all_for(#method, vec![
+                    #( Box::new(move |caller| Box::pin((#rules)(caller))) ),*
+                ]).await?;
+            });
+        }
+        if !args.auth_any.is_empty() {
+            let rules = &args.auth_any;
+            checks.push(quote! {
+                ::icu::auth::require_any_for(#method, vec![
+                    #( Box::new(move |caller| Box::pin((#rules)(caller))) ),*
+                ]).await?;
+            });
+        }
+        if let Some(expr) = &args.auth {
+            checks.push(quote! {
+                ::icu::auth::require_expr_for(#method, &{
+                    #[allow(unused_imports)]
+                    use ::icu::auth::expr::*;
+
+                    #expr
+                }).await?;
+            });
+        }
+
+        let block = &item.block;
+        item.block = syn::parse_quote!({
+            {
+                #[allow(unused_imports)]
+                use ::icu::auth::*;
+
+                #(#checks)*
+            }
+
+            #block
+        });
+    }
+
+    Ok(quote! {
+        #capability_guard
+        #cdk_attr
+        #item
+    })
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // expand_str
+    // the expansion as a string, or the error message
+    fn expand_str(
+        method: Method,
+        attr: TokenStream2,
+        item: TokenStream2,
+    ) -> Result<String, String> {
+        let item: ItemFn = syn::parse2(item).unwrap();
+
+        expand(method, attr, item)
+            .map(|tokens| tokens.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    fn update(attr: TokenStream2) -> Result<String, String> {
+        expand_str(
+            Method::Update,
+            attr,
+            quote!(
+                async fn endpoint() -> Result<(), Error> {
+                    Ok(())
+                }
+            ),
+        )
+    }
+
+    #[test]
+    fn default_guards() {
+        let out = update(quote!()).unwrap();
+        assert!(
+            out.contains(r#"guard = "::icu::guard::guard_update""#),
+            "{out}"
+        );
+
+        let out = expand_str(
+            Method::Query,
+            quote!(),
+            quote!(
+                fn endpoint() {}
+            ),
+        )
+        .unwrap();
+        assert!(
+            out.contains(r#"guard = "::icu::guard::guard_query""#),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn guard_argument() {
+        let out = update(quote!(guard = "query")).unwrap();
+        assert!(
+            out.contains(r#"guard = "::icu::guard::guard_query""#),
+            "{out}"
+        );
+
+        let out = update(quote!(guard = "none")).unwrap();
+        assert!(!out.contains("guard ="), "{out}");
+    }
+
+    #[test]
+    fn capability_gets_its_own_guard() {
+        let out = update(quote!(capability = "uploads")).unwrap();
+        assert!(out.contains("fn __icu_guard_endpoint"), "{out}");
+        assert!(out.contains(r#"guard = "__icu_guard_endpoint""#), "{out}");
+        assert!(
+            out.contains(r#"guard_update_capability ("uploads")"#),
+            "{out}"
+        );
+
+        let out = update(quote!(guard = "query", capability = "uploads")).unwrap();
+        assert!(
+            out.contains(r#"guard_query_capability ("uploads")"#),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn auth_arguments() {
+        let out = update(quote!(auth_any(is_parent, is_role("admin")))).unwrap();
+        assert!(out.contains(r#"require_any_for ("endpoint""#), "{out}");
+        assert!(out.contains(r#"is_role ("admin")"#), "{out}");
+        assert!(!out.contains("require_all_for"), "{out}");
+
+        let out = update(quote!(auth_all(is_controller))).unwrap();
+        assert!(out.contains(r#"require_all_for ("endpoint""#), "{out}");
+
+        let out = update(quote!(auth(any([controller(), not(anonymous())])))).unwrap();
+        assert!(out.contains(r#"require_expr_for ("endpoint""#), "{out}");
+        assert!(out.contains("use :: icu :: auth :: expr :: *"), "{out}");
+    }
+
+    #[test]
+    fn passthrough_arguments_reach_the_cdk() {
+        let out = update(quote!(name = "renamed", auth_any(is_parent))).unwrap();
+        assert!(
+            out.contains(r#"update (name = "renamed" , guard = "::icu::guard::guard_update")"#),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn rejected_input() {
+        for (attr, message) in [
+            (quote!(guard = "sometimes"), "guard must be"),
+            (quote!(guard = 1), "guard must be a string"),
+            (quote!(capability = uploads), "capability must be a string"),
+            (
+                quote!(guard = "none", capability = "uploads"),
+                "capability needs an update or query guard",
+            ),
+            (quote!(auth_any()), "at least one auth rule is required"),
+            (
+                quote!(auth(is_parent), auth(is_child)),
+                "auth can only be set once",
+            ),
+        ] {
+            let err = update(attr.clone()).unwrap_err();
+            assert!(err.contains(message), "{attr}: {err}");
+        }
+
+        let err = expand_str(
+            Method::Update,
+            quote!(auth_any(is_parent)),
+            quote!(
+                fn endpoint() {}
+            ),
+        )
+        .unwrap_err();
+        assert!(err.contains("has to be an async fn"), "{err}");
+    }
+}
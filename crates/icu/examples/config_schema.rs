@@ -0,0 +1,8 @@
+// config_schema
+// prints the JSON Schema for icu.toml, ie.
+// cargo run -p icu --example config_schema --features schema > crates/canisters/icu.schema.json
+fn main() {
+    let schema = icu::config::schema::json_schema();
+
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
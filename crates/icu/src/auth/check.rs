@@ -0,0 +1,189 @@
+use crate::{
+    Error,
+    auth::{AuthError, resolve_caller},
+    cdk::api::canister_self,
+    config::{Config, RateLimit},
+    memory::{
+        CanisterChildren, CanisterDirectory, CanisterRegistry, CanisterState, RoleRegistry,
+        Whitelist,
+    },
+    state::rate_limit::{RateLimitKey, RateLimiter},
+    types::CanisterType,
+};
+use candid::Principal;
+
+//
+// CHECKS
+// the synchronous side of the auth rules, nothing here needs to await so
+// AuthExpr can run them without building a future for each one
+//
+
+// anonymous
+pub fn anonymous(caller: Principal) -> Result<(), Error> {
+    if caller == Principal::anonymous() {
+        Ok(())
+    } else {
+        Err(AuthError::NotAnonymous(caller))?
+    }
+}
+
+// app
+pub fn app(caller: Principal) -> Result<(), Error> {
+    match CanisterRegistry::get(caller) {
+        Some(_) => Ok(()),
+        None => Err(AuthError::NotApp(caller))?,
+    }
+}
+
+// canister_type
+// the caller has to be a known canister of that type, checked against everything
+// this canister knows about: the directory, its children, its parents and
+// (on root) the registry, so types that don't use the directory work too
+pub fn canister_type(caller: Principal, ty: &CanisterType) -> Result<(), Error> {
+    if caller_has_type(caller, ty) {
+        Ok(())
+    } else {
+        Err(AuthError::NotCanisterType(caller, ty.clone()))?
+    }
+}
+
+// caller_has_type
+fn caller_has_type(caller: Principal, ty: &CanisterType) -> bool {
+    if caller == canister_self() {
+        return CanisterState::get_type().as_ref() == Some(ty);
+    }
+
+    if CanisterDirectory::get(ty).is_some_and(|entry| entry.canisters.contains(&caller)) {
+        return true;
+    }
+
+    if CanisterChildren::get(&caller).as_ref() == Some(ty) {
+        return true;
+    }
+
+    if CanisterState::get_parents()
+        .iter()
+        .any(|p| p.principal == caller && &p.canister_type == ty)
+    {
+        return true;
+    }
+
+    CanisterState::is_root()
+        && CanisterRegistry::get(caller).is_some_and(|entry| &entry.canister_type == ty)
+}
+
+// child
+pub fn child(caller: Principal) -> Result<(), Error> {
+    CanisterChildren::get(&caller).ok_or(AuthError::NotChild(caller))?;
+
+    Ok(())
+}
+
+// controller
+pub fn controller(caller: Principal) -> Result<(), Error> {
+    if crate::cdk::api::is_controller(&caller) {
+        Ok(())
+    } else {
+        Err(AuthError::NotController(caller))?
+    }
+}
+
+// delegated
+pub fn delegated(caller: Principal) -> Result<(), Error> {
+    match resolve_caller(caller) {
+        Some(_) => Ok(()),
+        None => Err(AuthError::NotDelegated(caller))?,
+    }
+}
+
+// parent
+pub fn parent(caller: Principal) -> Result<(), Error> {
+    if CanisterState::has_parent_pid(&caller) {
+        Ok(())
+    } else {
+        Err(AuthError::NotParent(caller))?
+    }
+}
+
+// principal
+pub fn principal(caller: Principal, expected: Principal) -> Result<(), Error> {
+    if caller == expected {
+        Ok(())
+    } else {
+        Err(AuthError::NotPrincipal(caller, expected))?
+    }
+}
+
+// rate_limit
+// takes a token from the caller's bucket
+pub fn rate_limit(caller: Principal, method: &str) -> Result<(), Error> {
+    rate_limit_with(caller, method, RateLimiter::check)
+}
+
+// rate_limit_peek
+// the same answer as rate_limit, but the token stays in the bucket
+pub fn rate_limit_peek(caller: Principal, method: &str) -> Result<(), Error> {
+    rate_limit_with(caller, method, RateLimiter::peek)
+}
+
+// rate_limit_with
+fn rate_limit_with(
+    caller: Principal,
+    method: &str,
+    take: fn(RateLimitKey, RateLimit) -> bool,
+) -> Result<(), Error> {
+    let config = Config::try_get()?;
+    let limits = &config.rate_limit;
+
+    let (key, limit) = if let Some(limit) = limits.methods.get(method) {
+        ((caller, Some(method.to_string())), *limit)
+    } else if let Some(limit) = limits.default {
+        let key_method = limits.per_method.then(|| method.to_string());
+        ((caller, key_method), limit)
+    } else {
+        return Ok(());
+    };
+
+    if take(key, limit) {
+        Ok(())
+    } else {
+        Err(AuthError::RateLimited(caller, method.to_string()))?
+    }
+}
+
+// role
+pub fn role(caller: Principal, role: &str) -> Result<(), Error> {
+    if RoleRegistry::has_role(role, &caller) {
+        Ok(())
+    } else {
+        Err(AuthError::NotRole(caller, role.to_string()))?
+    }
+}
+
+// root
+pub fn root(caller: Principal) -> Result<(), Error> {
+    if caller == CanisterState::get_root_pid() {
+        Ok(())
+    } else {
+        Err(AuthError::NotRoot(caller))?
+    }
+}
+
+// same_canister
+pub fn same_canister(caller: Principal) -> Result<(), Error> {
+    if caller == canister_self() {
+        Ok(())
+    } else {
+        Err(AuthError::NotSameCanister(caller))?
+    }
+}
+
+// whitelisted
+// passes everyone while the whitelist is disabled
+pub fn whitelisted(caller: Principal) -> Result<(), Error> {
+    if Whitelist::is_allowed(&caller) {
+        Ok(())
+    } else {
+        Err(AuthError::NotWhitelisted(caller))?
+    }
+}
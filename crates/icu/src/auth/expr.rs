@@ -0,0 +1,515 @@
+use crate::{
+    Error,
+    auth::{AuthError, RuleFn, check},
+    types::CanisterType,
+};
+use candid::Principal;
+use std::{fmt, pin::Pin};
+
+///
+/// CheckFn
+/// a synchronous rule, no future is built to run it
+///
+
+pub type CheckFn = fn(Principal) -> Result<(), Error>;
+
+///
+/// AuthExpr
+///
+/// ie. "controller OR (parent AND whitelisted)" and "NOT anonymous"
+///
+/// any([controller(), all([parent(), whitelisted()])])
+/// not(anonymous())
+///
+/// RateLimit only peeks at the bucket while the expression runs, the token is
+/// taken once the whole expression has passed
+///
+
+pub enum AuthExpr {
+    Check(&'static str, CheckFn),
+    CheckWith(
+        String,
+        Box<dyn Fn(Principal) -> Result<(), Error> + Send + Sync>,
+    ),
+    RateLimit(String),
+    Rule(String, RuleFn),
+    All(Vec<Self>),
+    Any(Vec<Self>),
+    Not(Box<Self>),
+}
+
+///
+/// Failure
+/// why part of an expression didn't pass.  Only a denial (RateLimited is one) is
+/// turned round by not(), an Error (ie. the config isn't loaded) fails the branch
+/// whatever wraps it
+///
+
+enum Failure {
+    Denied(String),
+    RateLimited(String),
+    Error(String),
+}
+
+impl Failure {
+    fn into_message(self) -> String {
+        match self {
+            Self::Denied(msg) | Self::RateLimited(msg) | Self::Error(msg) => msg,
+        }
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::AuthError(msg) => Self::Denied(msg),
+            Error::RateLimited(msg) => Self::RateLimited(msg),
+            e => Self::Error(e.to_string()),
+        }
+    }
+}
+
+// Spent
+// the rate limits that passed on the way to the result, charged at the end
+type Spent = Vec<String>;
+
+impl AuthExpr {
+    // is_sync
+    // true if nothing in the expression has to await
+    #[must_use]
+    pub fn is_sync(&self) -> bool {
+        match self {
+            Self::Check(..) | Self::CheckWith(..) | Self::RateLimit(..) => true,
+            Self::Rule(..) => false,
+            Self::All(exprs) | Self::Any(exprs) => exprs.iter().all(Self::is_sync),
+            Self::Not(expr) => expr.is_sync(),
+        }
+    }
+
+    // check
+    // evaluates a synchronous expression, errors if it contains an async rule
+    pub fn check(&self, caller: Principal) -> Result<(), Error> {
+        if !self.is_sync() {
+            Err(AuthError::AsyncExpr(self.to_string()))?;
+        }
+
+        let mut spent = Spent::new();
+        let res = self.eval_sync(caller, &mut spent);
+
+        finish(caller, res, &spent)
+    }
+
+    // eval
+    // synchronous expressions don't allocate a future at all
+    pub async fn eval(&self, caller: Principal) -> Result<(), Error> {
+        let mut spent = Spent::new();
+        let res = if self.is_sync() {
+            self.eval_sync(caller, &mut spent)
+        } else {
+            self.eval_async(caller, &mut spent).await
+        };
+
+        finish(caller, res, &spent)
+    }
+
+    // eval_sync
+    fn eval_sync(&self, caller: Principal, spent: &mut Spent) -> Result<(), Failure> {
+        match self {
+            Self::Check(_, f) => Ok(f(caller)?),
+            Self::CheckWith(_, f) => Ok(f(caller)?),
+            Self::RateLimit(method) => {
+                check::rate_limit_peek(caller, method)?;
+                spent.push(method.clone());
+
+                Ok(())
+            }
+            Self::Rule(name, _) => Err(Failure::Error(format!("{name} has to be awaited"))),
+            Self::All(exprs) => {
+                non_empty(exprs)?;
+                exprs.iter().try_for_each(|e| e.eval_sync(caller, spent))
+            }
+            Self::Any(exprs) => {
+                non_empty(exprs)?;
+                let mut failures = Vec::new();
+                for expr in exprs {
+                    let mark = spent.len();
+                    match expr.eval_sync(caller, spent) {
+                        Ok(()) => return Ok(()),
+                        Err(f) => {
+                            spent.truncate(mark);
+                            failures.push(f);
+                        }
+                    }
+                }
+
+                Err(any_failure(failures))
+            }
+            Self::Not(expr) => {
+                let mark = spent.len();
+                let res = expr.eval_sync(caller, spent);
+                spent.truncate(mark);
+
+                not_result(expr, caller, res)
+            }
+        }
+    }
+
+    // eval_async
+    // only boxes the nodes that actually contain an async rule
+    fn eval_async<'a>(
+        &'a self,
+        caller: Principal,
+        spent: &'a mut Spent,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Failure>> + 'a>> {
+        Box::pin(async move {
+            match self {
+                Self::Rule(_, f) => Ok(f(caller).await?),
+                Self::All(exprs) => {
+                    non_empty(exprs)?;
+                    for expr in exprs {
+                        expr.eval_either(caller, spent).await?;
+                    }
+
+                    Ok(())
+                }
+                Self::Any(exprs) => {
+                    non_empty(exprs)?;
+                    let mut failures = Vec::new();
+                    for expr in exprs {
+                        let mark = spent.len();
+                        match expr.eval_either(caller, spent).await {
+                            Ok(()) => return Ok(()),
+                            Err(f) => {
+                                spent.truncate(mark);
+                                failures.push(f);
+                            }
+                        }
+                    }
+
+                    Err(any_failure(failures))
+                }
+                Self::Not(expr) => {
+                    let mark = spent.len();
+                    let res = expr.eval_either(caller, spent).await;
+                    spent.truncate(mark);
+
+                    not_result(expr, caller, res)
+                }
+                _ => self.eval_sync(caller, spent),
+            }
+        })
+    }
+
+    // eval_either
+    async fn eval_either(&self, caller: Principal, spent: &mut Spent) -> Result<(), Failure> {
+        if self.is_sync() {
+            self.eval_sync(caller, spent)
+        } else {
+            self.eval_async(caller, spent).await
+        }
+    }
+}
+
+impl fmt::Display for AuthExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter<'_>, name: &str, exprs: &[Self]| {
+            let inner: Vec<_> = exprs.iter().map(ToString::to_string).collect();
+            write!(f, "{name}({})", inner.join(", "))
+        };
+
+        match self {
+            Self::Check(name, _) => write!(f, "{name}"),
+            Self::CheckWith(name, _) | Self::Rule(name, _) => write!(f, "{name}"),
+            Self::RateLimit(method) => write!(f, "rate_limit({method})"),
+            Self::All(exprs) => list(f, "all", exprs),
+            Self::Any(exprs) => list(f, "any", exprs),
+            Self::Not(expr) => write!(f, "not({expr})"),
+        }
+    }
+}
+
+// finish
+// the expression passed, so the rate limits on the deciding branches take their tokens
+fn finish(caller: Principal, res: Result<(), Failure>, spent: &[String]) -> Result<(), Error> {
+    match res {
+        Ok(()) => {}
+        Err(Failure::RateLimited(msg)) => {
+            Err(Error::RateLimited(AuthError::ExprFailed(msg).to_string()))?;
+        }
+        Err(f) => Err(AuthError::ExprFailed(f.into_message()))?,
+    }
+
+    for method in spent {
+        check::rate_limit(caller, method)?;
+    }
+
+    Ok(())
+}
+
+// non_empty
+fn non_empty(exprs: &[AuthExpr]) -> Result<(), Failure> {
+    if exprs.is_empty() {
+        Err(Failure::Error(AuthError::NoRulesDefined.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+// any_failure
+// every branch failed, if one of them was an error the whole thing is, otherwise
+// it's rate limited if a rate limit was in the way
+fn any_failure(failures: Vec<Failure>) -> Failure {
+    let is_error = failures.iter().any(|f| matches!(f, Failure::Error(_)));
+    let is_rate_limited = failures
+        .iter()
+        .any(|f| matches!(f, Failure::RateLimited(_)));
+    let messages: Vec<_> = failures.into_iter().map(Failure::into_message).collect();
+    let msg = format!("none of [{}]", messages.join("; "));
+
+    if is_error {
+        Failure::Error(msg)
+    } else if is_rate_limited {
+        Failure::RateLimited(msg)
+    } else {
+        Failure::Denied(msg)
+    }
+}
+
+// not_result
+// only a denial is inverted, errors go straight through
+fn not_result(expr: &AuthExpr, caller: Principal, res: Result<(), Failure>) -> Result<(), Failure> {
+    match res {
+        Ok(()) => Err(Failure::Denied(format!(
+            "caller '{caller}' matched not({expr})"
+        ))),
+        Err(Failure::Denied(_) | Failure::RateLimited(_)) => Ok(()),
+        Err(e @ Failure::Error(_)) => Err(e),
+    }
+}
+
+//
+// BUILDERS
+//
+
+#[must_use]
+pub fn all(exprs: impl IntoIterator<Item = AuthExpr>) -> AuthExpr {
+    AuthExpr::All(exprs.into_iter().collect())
+}
+
+#[must_use]
+pub fn any(exprs: impl IntoIterator<Item = AuthExpr>) -> AuthExpr {
+    AuthExpr::Any(exprs.into_iter().collect())
+}
+
+#[must_use]
+pub fn not(expr: AuthExpr) -> AuthExpr {
+    AuthExpr::Not(Box::new(expr))
+}
+
+// rule
+// wraps one of the async rule functions, ie. rule("is_parent", Box::new(is_parent))
+#[must_use]
+pub fn rule(name: &str, f: RuleFn) -> AuthExpr {
+    AuthExpr::Rule(name.to_string(), f)
+}
+
+#[must_use]
+pub fn anonymous() -> AuthExpr {
+    AuthExpr::Check("anonymous", check::anonymous)
+}
+
+#[must_use]
+pub fn app() -> AuthExpr {
+    AuthExpr::Check("app", check::app)
+}
+
+#[must_use]
+pub fn child() -> AuthExpr {
+    AuthExpr::Check("child", check::child)
+}
+
+#[must_use]
+pub fn controller() -> AuthExpr {
+    AuthExpr::Check("controller", check::controller)
+}
+
+#[must_use]
+pub fn delegated() -> AuthExpr {
+    AuthExpr::Check("delegated", check::delegated)
+}
+
+#[must_use]
+pub fn parent() -> AuthExpr {
+    AuthExpr::Check("parent", check::parent)
+}
+
+#[must_use]
+pub fn root() -> AuthExpr {
+    AuthExpr::Check("root", check::root)
+}
+
+#[must_use]
+pub fn same_canister() -> AuthExpr {
+    AuthExpr::Check("same_canister", check::same_canister)
+}
+
+#[must_use]
+pub fn whitelisted() -> AuthExpr {
+    AuthExpr::Check("whitelisted", check::whitelisted)
+}
+
+#[must_use]
+pub fn canister_type(ty: CanisterType) -> AuthExpr {
+    AuthExpr::CheckWith(
+        format!("canister_type({ty})"),
+        Box::new(move |caller| check::canister_type(caller, &ty)),
+    )
+}
+
+#[must_use]
+pub fn principal(expected: Principal) -> AuthExpr {
+    AuthExpr::CheckWith(
+        format!("principal({expected})"),
+        Box::new(move |caller| check::principal(caller, expected)),
+    )
+}
+
+#[must_use]
+pub fn rate_limit(method: &str) -> AuthExpr {
+    AuthExpr::RateLimit(method.to_string())
+}
+
+#[must_use]
+pub fn role(role: &str) -> AuthExpr {
+    let role = role.to_string();
+
+    AuthExpr::CheckWith(
+        format!("role({role})"),
+        Box::new(move |caller| check::role(caller, &role)),
+    )
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ConfigError};
+
+    fn yes() -> AuthExpr {
+        AuthExpr::Check("yes", |_| Ok(()))
+    }
+
+    fn no() -> AuthExpr {
+        AuthExpr::Check("no", |_| Err(AuthError::custom("said no"))?)
+    }
+
+    fn caller() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    fn broken() -> AuthExpr {
+        AuthExpr::Check("broken", |_| Err(ConfigError::NotInitialized)?)
+    }
+
+    #[test]
+    fn combinators() {
+        assert!(all([yes(), yes()]).check(caller()).is_ok());
+        assert!(all([yes(), no()]).check(caller()).is_err());
+        assert!(any([no(), yes()]).check(caller()).is_ok());
+        assert!(any([no(), no()]).check(caller()).is_err());
+        assert!(not(no()).check(caller()).is_ok());
+        assert!(not(yes()).check(caller()).is_err());
+
+        // empty lists never pass
+        assert!(all([]).check(caller()).is_err());
+        assert!(any([]).check(caller()).is_err());
+    }
+
+    #[test]
+    fn nested() {
+        // no OR (yes AND NOT no)
+        let expr = any([no(), all([yes(), not(no())])]);
+        assert!(expr.check(caller()).is_ok());
+        assert_eq!(expr.to_string(), "any(no, all(yes, not(no)))");
+
+        let expr = any([no(), all([yes(), not(yes())])]);
+        assert!(expr.check(caller()).is_err());
+    }
+
+    #[test]
+    fn errors_name_the_failed_branches() {
+        let err = any([no(), not(yes())]).check(caller()).unwrap_err();
+        let msg = err.to_string();
+
+        assert!(msg.contains("said no"));
+        assert!(msg.contains("matched not(yes)"));
+    }
+
+    #[test]
+    fn async_rules_need_eval() {
+        let expr = any([
+            yes(),
+            rule("async", Box::new(|_| Box::pin(async { Ok(()) }))),
+        ]);
+
+        assert!(!expr.is_sync());
+        assert!(expr.check(caller()).is_err());
+    }
+
+    #[test]
+    fn not_only_inverts_denials() {
+        assert!(not(broken()).check(caller()).is_err());
+        assert!(not(all([yes(), broken()])).check(caller()).is_err());
+
+        // another branch can still pass
+        assert!(any([broken(), yes()]).check(caller()).is_ok());
+        assert!(any([broken(), no()]).check(caller()).is_err());
+    }
+
+    #[test]
+    fn rate_limits_are_only_spent_when_they_decide() {
+        Config::init_from_toml("[rate_limit.methods.foo]\ncapacity = 1\nrefill_secs = 3600")
+            .unwrap();
+
+        // the failed branch and the not() don't take the only token
+        assert!(
+            any([all([rate_limit("foo"), no()]), yes()])
+                .check(caller())
+                .is_ok()
+        );
+        assert!(
+            all([yes(), not(rate_limit("foo"))])
+                .check(caller())
+                .is_err()
+        );
+        assert!(
+            any([all([rate_limit("foo"), no()]), no()])
+                .check(caller())
+                .is_err()
+        );
+
+        assert!(all([rate_limit("foo"), yes()]).check(caller()).is_ok());
+        assert!(all([rate_limit("foo"), yes()]).check(caller()).is_err());
+        assert_eq!(rate_limit("foo").to_string(), "rate_limit(foo)");
+    }
+
+    #[test]
+    fn rate_limited_failures_stay_typed() {
+        Config::init_from_toml("[rate_limit.methods.foo]\ncapacity = 1\nrefill_secs = 3600")
+            .unwrap();
+        assert!(rate_limit("foo").check(caller()).is_ok());
+
+        // over the limit, on its own or as the reason every branch failed
+        for expr in [rate_limit("foo"), any([rate_limit("foo"), no()])] {
+            let err = expr.check(caller()).unwrap_err();
+            assert!(matches!(err, Error::RateLimited(_)), "{expr}: {err:?}");
+        }
+
+        // a plain denial is still an AuthError
+        let err = no().check(caller()).unwrap_err();
+        assert!(matches!(err, Error::AuthError(_)), "{err:?}");
+    }
+}
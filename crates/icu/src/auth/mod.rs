@@ -0,0 +1,555 @@
+pub mod check;
+pub mod expr;
+
+use crate::{
+    Error,
+    auth::expr::AuthExpr,
+    cdk::api::{in_replicated_execution, msg_caller},
+    config::{AuthRule, Config},
+    memory::AuditLog,
+    state::delegation::{DelegationCache, DelegationRegistry},
+    types::CanisterType,
+    utils::time::now_secs,
+};
+use candid::Principal;
+use std::pin::Pin;
+use thiserror::Error as ThisError;
+
+///
+/// AuthError
+///
+
+#[derive(Debug, ThisError)]
+pub enum AuthError {
+    #[error("{0}")]
+    Custom(String),
+
+    #[error("auth expression {0} has async rules, use eval")]
+    AsyncExpr(String),
+
+    #[error("endpoint {0} is disabled")]
+    EndpointDisabled(String),
+
+    #[error("auth failed: {0}")]
+    ExprFailed(String),
+
+    #[error("invalid error state - this should never happen")]
+    InvalidState,
+
+    #[error("one or more rules must be defined")]
+    NoRulesDefined,
+
+    #[error("caller '{0}' is not anonymous")]
+    NotAnonymous(Principal),
+
+    #[error("caller '{0}' is not an application canister on this subnet")]
+    NotApp(Principal),
+
+    #[error("caller '{0}' does not match canister type '{1}'")]
+    NotCanisterType(Principal, CanisterType),
+
+    #[error("caller '{0}' is not a child of this canister")]
+    NotChild(Principal),
+
+    #[error("caller '{0}' is not a controller of this canister")]
+    NotController(Principal),
+
+    #[error("caller '{0}' is not a valid delegated session")]
+    NotDelegated(Principal),
+
+    #[error("caller '{0}' is not the parent of this canister")]
+    NotParent(Principal),
+
+    #[error("caller '{0}' does not have role '{1}'")]
+    NotRole(Principal, String),
+
+    #[error("expected caller principal '{1}' got '{0}'")]
+    NotPrincipal(Principal, Principal),
+
+    #[error("caller '{0}' is over the rate limit for '{1}'")]
+    RateLimited(Principal, String),
+
+    #[error("caller '{0}' is not root")]
+    NotRoot(Principal),
+
+    #[error("caller '{0}' is not the current canister")]
+    NotSameCanister(Principal),
+
+    #[error("caller '{0}' is not on the whitelist")]
+    NotWhitelisted(Principal),
+}
+
+impl AuthError {
+    #[must_use]
+    pub fn custom(s: &str) -> Self {
+        Self::Custom(s.to_string())
+    }
+}
+
+///
+/// Rule
+///
+
+pub type RuleFn =
+    Box<dyn Fn(Principal) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> + Send + Sync>;
+
+pub type RuleResult = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+///
+/// Auth Functions
+///
+
+// require_all
+pub async fn require_all(rules: Vec<RuleFn>) -> Result<(), Error> {
+    audit_denied(None, check_all(rules).await)
+}
+
+// require_all_for
+// same as require_all, but the method name goes in the audit log
+pub async fn require_all_for(method: &str, rules: Vec<RuleFn>) -> Result<(), Error> {
+    audit_denied(Some(method), check_all(rules).await)
+}
+
+// require_any
+pub async fn require_any(rules: Vec<RuleFn>) -> Result<(), Error> {
+    audit_denied(None, check_any(rules).await)
+}
+
+// require_any_for
+pub async fn require_any_for(method: &str, rules: Vec<RuleFn>) -> Result<(), Error> {
+    audit_denied(Some(method), check_any(rules).await)
+}
+
+// require_expr
+pub async fn require_expr(expr: &AuthExpr) -> Result<(), Error> {
+    audit_denied(None, expr.eval(msg_caller()).await)
+}
+
+// require_expr_for
+pub async fn require_expr_for(method: &str, expr: &AuthExpr) -> Result<(), Error> {
+    audit_denied(Some(method), expr.eval(msg_caller()).await)
+}
+
+// check_all
+async fn check_all(rules: Vec<RuleFn>) -> Result<(), Error> {
+    let caller = msg_caller();
+
+    if rules.is_empty() {
+        return Err(AuthError::NoRulesDefined.into());
+    }
+
+    for rule in rules {
+        rule(caller).await?; // early return on failure
+    }
+
+    Ok(())
+}
+
+// check_any
+async fn check_any(rules: Vec<RuleFn>) -> Result<(), Error> {
+    let caller = msg_caller();
+
+    if rules.is_empty() {
+        return Err(AuthError::NoRulesDefined.into());
+    }
+
+    let mut last_error = None;
+    for rule in rules {
+        match rule(caller).await {
+            Ok(()) => return Ok(()),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| AuthError::InvalidState.into()))
+}
+
+// audit_denied
+// rate limited callers are expected and would flood the ring, and anything
+// recorded in a query is thrown away with the rest of its state, so neither is kept
+fn audit_denied(method: Option<&str>, res: Result<(), Error>) -> Result<(), Error> {
+    if let Err(e) = &res
+        && !is_rate_limited(e)
+        && in_replicated_execution()
+    {
+        AuditLog::record_denied(msg_caller(), method, e.to_string());
+    }
+
+    res
+}
+
+// is_rate_limited
+const fn is_rate_limited(e: &Error) -> bool {
+    matches!(e, Error::RateLimited(_))
+}
+
+// require_endpoint
+// the auth for a built-in endpoint, [endpoints] in the config can override the
+// default rules or disable the endpoint entirely
+pub async fn require_endpoint(name: &str, default: &[AuthRule]) -> Result<(), Error> {
+    let config = Config::try_get()?;
+    let endpoint = config.endpoints.get(name);
+
+    if endpoint.is_some_and(|e| e.disabled) {
+        return audit_denied(
+            Some(name),
+            Err(AuthError::EndpointDisabled(name.to_string()).into()),
+        );
+    }
+
+    let rules = endpoint.and_then(|e| e.auth.as_deref()).unwrap_or(default);
+    if rules.contains(&AuthRule::Public) {
+        return Ok(());
+    }
+
+    require_any_for(name, rules.iter().map(rule_fn).collect()).await
+}
+
+// require_internal
+// the plumbing endpoints, the rules are fixed and [endpoints] is never consulted
+pub async fn require_internal(name: &str, rules: &[AuthRule]) -> Result<(), Error> {
+    require_any_for(name, rules.iter().map(rule_fn).collect()).await
+}
+
+// rule_fn
+fn rule_fn(rule: &AuthRule) -> RuleFn {
+    match rule {
+        AuthRule::Public => Box::new(|_| Box::pin(async { Ok(()) })),
+        AuthRule::App => Box::new(is_app),
+        AuthRule::Child => Box::new(is_child),
+        AuthRule::Controller => Box::new(is_controller),
+        AuthRule::Parent => Box::new(is_parent),
+        AuthRule::Root => Box::new(is_root),
+        AuthRule::SameCanister => Box::new(is_same_canister),
+        AuthRule::Whitelisted => Box::new(is_whitelisted),
+        AuthRule::Role(role) => Box::new(is_role(role)),
+    }
+}
+
+// resolve_caller
+// the wallet behind a valid, unexpired session key, or None if the caller isn't one.
+// Sessions registered on this canister are checked first, then the DelegationCache
+// for sessions that live on another canister.  Only one hop is followed, a session
+// registered by another session key resolves to that key and not its wallet, and a
+// wallet that delegated to itself isn't a session key
+#[must_use]
+pub fn resolve_caller(caller: Principal) -> Option<Principal> {
+    let wallet_pid = DelegationRegistry::resolve_wallet(caller)
+        .ok()
+        .or_else(|| {
+            DelegationCache::get(caller)
+                .filter(|session| session.expires_at > now_secs())
+                .map(|session| session.wallet_pid)
+        })?;
+
+    (wallet_pid != caller).then_some(wallet_pid)
+}
+
+// effective_caller
+// the wallet if the caller is a session key, otherwise the caller itself
+#[must_use]
+pub fn effective_caller(caller: Principal) -> Principal {
+    resolve_caller(caller).unwrap_or(caller)
+}
+
+///
+/// RULE MACROS
+///
+
+#[macro_export]
+macro_rules! auth_require_all {
+    ($($f:expr),* $(,)?) => {{
+        $crate::auth::require_all(vec![
+            $( Box::new(move |caller| Box::pin($f(caller))) ),*
+        ]).await
+    }};
+}
+
+// auth_endpoint
+// ie. auth_endpoint!("icu_app", Controller)
+#[macro_export]
+macro_rules! auth_endpoint {
+    ($name:literal, $($rule:ident),* $(,)?) => {{
+        $crate::auth::require_endpoint($name, &[$($crate::config::AuthRule::$rule),*]).await
+    }};
+}
+
+// auth_internal
+// ie. auth_internal!("icu_state_update", Parent)
+#[macro_export]
+macro_rules! auth_internal {
+    ($name:literal, $($rule:ident),* $(,)?) => {{
+        $crate::auth::require_internal($name, &[$($crate::config::AuthRule::$rule),*]).await
+    }};
+}
+
+#[macro_export]
+macro_rules! auth_require_any {
+    ($($f:expr),* $(,)?) => {{
+        $crate::auth::require_any(vec![
+            $( Box::new(move |caller| Box::pin($f(caller))) ),*
+        ]).await
+    }};
+}
+
+///
+/// RULE FUNCTIONS
+///
+
+// is_app
+#[must_use]
+pub fn is_app(caller: Principal) -> RuleResult {
+    Box::pin(async move { check::app(caller) })
+}
+
+// is_canister_type
+#[must_use]
+pub fn is_canister_type(caller: Principal, ty: CanisterType) -> RuleResult {
+    Box::pin(async move { check::canister_type(caller, &ty) })
+}
+
+// is_child
+#[must_use]
+pub fn is_child(caller: Principal) -> RuleResult {
+    Box::pin(async move { check::child(caller) })
+}
+
+// is_delegated
+// the caller has to be a valid session key
+#[must_use]
+pub fn is_delegated(caller: Principal) -> RuleResult {
+    Box::pin(async move { check::delegated(caller) })
+}
+
+// as_wallet
+// runs another rule against the effective caller, so session keys are
+// checked as the wallet that created them,
+// ie. auth_require_any!(as_wallet(is_whitelisted), as_wallet(is_role("admin")))
+pub fn as_wallet<F>(rule: F) -> impl Fn(Principal) -> RuleResult + Send + Sync + 'static
+where
+    F: Fn(Principal) -> RuleResult + Send + Sync + 'static,
+{
+    move |caller| rule(effective_caller(caller))
+}
+
+// is_controller
+#[must_use]
+pub fn is_controller(caller: Principal) -> RuleResult {
+    Box::pin(async move { check::controller(caller) })
+}
+
+// is_root
+#[must_use]
+pub fn is_root(caller: Principal) -> RuleResult {
+    Box::pin(async move { check::root(caller) })
+}
+
+// is_parent
+#[must_use]
+pub fn is_parent(caller: Principal) -> RuleResult {
+    Box::pin(async move { check::parent(caller) })
+}
+
+// is_principal
+#[must_use]
+pub fn is_principal(caller: Principal, expected: Principal) -> RuleResult {
+    Box::pin(async move { check::principal(caller, expected) })
+}
+
+// rate_limit
+// the method name has to be passed in as it's only available in inspect_message,
+// ie. auth_require_all!(is_parent, rate_limit("my_method"))
+// only works in updates, a query can't change the buckets
+pub fn rate_limit(method: &str) -> impl Fn(Principal) -> RuleResult + Send + Sync + 'static {
+    let method = method.to_string();
+
+    move |caller| {
+        let method = method.clone();
+
+        Box::pin(async move { check::rate_limit(caller, &method) })
+    }
+}
+
+// is_role
+// returns the rule so it can be used directly, ie. auth_require_any!(is_role("moderator"))
+pub fn is_role(role: &str) -> impl Fn(Principal) -> RuleResult + Send + Sync + 'static {
+    let role = role.to_string();
+
+    move |caller| {
+        let role = role.clone();
+
+        Box::pin(async move { check::role(caller, &role) })
+    }
+}
+
+// is_same_canister
+#[must_use]
+pub fn is_same_canister(caller: Principal) -> RuleResult {
+    Box::pin(async move { check::same_canister(caller) })
+}
+
+// is_whitelisted
+// passes everyone while the whitelist is disabled
+#[must_use]
+pub fn is_whitelisted(caller: Principal) -> RuleResult {
+    Box::pin(async move { check::whitelisted(caller) })
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::delegation::{DelegationSessionView, RegisterSessionArgs};
+    use std::task::{Context, Poll, Waker};
+
+    fn pid(n: u8) -> Principal {
+        Principal::from_slice(&[n])
+    }
+
+    // register
+    // wallet registers session for ten minutes, and the session key confirms it
+    fn register(wallet: Principal, session: Principal) {
+        DelegationRegistry::register_session(
+            wallet,
+            RegisterSessionArgs {
+                session_pid: session,
+                duration_secs: 600,
+            },
+        )
+        .unwrap();
+        DelegationRegistry::confirm_session(session, wallet).unwrap();
+    }
+
+    // run
+    // the sync rules are ready on the first poll
+    fn run(mut rule: RuleResult) -> Result<(), Error> {
+        match rule.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(res) => res,
+            Poll::Pending => panic!("rule didn't complete"),
+        }
+    }
+
+    // is_wallet
+    // as_wallet(rule) that only passes for the wallet
+    fn is_wallet(wallet: Principal) -> impl Fn(Principal) -> RuleResult {
+        as_wallet(move |caller| is_principal(caller, wallet))
+    }
+
+    #[test]
+    fn rate_limits_are_recognised() {
+        let pid = Principal::anonymous();
+
+        assert!(is_rate_limited(
+            &AuthError::RateLimited(pid, "foo".to_string()).into()
+        ));
+        assert!(!is_rate_limited(&AuthError::NotController(pid).into()));
+
+        // the variant decides, not the wording
+        assert!(!is_rate_limited(
+            &AuthError::Custom("caller 'x' is over the rate limit for 'foo'".to_string()).into()
+        ));
+    }
+
+    #[test]
+    fn delegated_caller_acts_as_wallet() {
+        let (wallet, session) = (pid(1), pid(2));
+        register(wallet, session);
+
+        assert_eq!(resolve_caller(session), Some(wallet));
+        assert_eq!(effective_caller(session), wallet);
+        assert!(check::delegated(session).is_ok());
+        assert!(run(is_wallet(wallet)(session)).is_ok());
+
+        // the wallet is still itself, it just isn't delegated
+        assert_eq!(effective_caller(wallet), wallet);
+        assert!(check::delegated(wallet).is_err());
+        assert!(run(is_wallet(wallet)(wallet)).is_ok());
+    }
+
+    #[test]
+    fn cached_sessions_resolve_until_they_expire() {
+        let (wallet, session, expired) = (pid(1), pid(2), pid(3));
+        let view = |session_pid, expires_at| DelegationSessionView {
+            wallet_pid: wallet,
+            session_pid,
+            expires_at,
+            is_expired: false,
+        };
+        DelegationCache::insert(session, view(session, now_secs() + 600));
+        DelegationCache::insert(expired, view(expired, now_secs() - 1));
+
+        assert_eq!(resolve_caller(session), Some(wallet));
+        assert_eq!(resolve_caller(expired), None);
+        assert!(check::delegated(expired).is_err());
+    }
+
+    #[test]
+    fn unregistered_delegate_is_itself() {
+        let (wallet, stranger) = (pid(1), pid(2));
+        register(wallet, pid(3));
+
+        assert_eq!(resolve_caller(stranger), None);
+        assert_eq!(effective_caller(stranger), stranger);
+        assert!(check::delegated(stranger).is_err());
+        assert!(run(is_wallet(wallet)(stranger)).is_err());
+    }
+
+    #[test]
+    fn self_delegation_isnt_a_session() {
+        let wallet = pid(1);
+        let args = RegisterSessionArgs {
+            session_pid: wallet,
+            duration_secs: 600,
+        };
+        assert!(DelegationRegistry::register_session(wallet, args).is_err());
+
+        // a cached one from elsewhere doesn't count either
+        DelegationCache::insert(
+            wallet,
+            DelegationSessionView {
+                wallet_pid: wallet,
+                session_pid: wallet,
+                expires_at: now_secs() + 600,
+                is_expired: false,
+            },
+        );
+
+        assert_eq!(resolve_caller(wallet), None);
+        assert!(check::delegated(wallet).is_err());
+        assert!(run(is_wallet(wallet)(wallet)).is_ok());
+    }
+
+    #[test]
+    fn unconfirmed_claims_dont_resolve() {
+        let (attacker, victim) = (pid(1), pid(2));
+        let args = RegisterSessionArgs {
+            session_pid: victim,
+            duration_secs: 600,
+        };
+        DelegationRegistry::register_session(attacker, args).unwrap();
+
+        // the victim never confirmed, so they're still checked as themselves
+        assert_eq!(resolve_caller(victim), None);
+        assert_eq!(effective_caller(victim), victim);
+        assert!(run(is_wallet(victim)(victim)).is_ok());
+        assert!(run(is_wallet(attacker)(victim)).is_err());
+    }
+
+    #[test]
+    fn nested_delegation_only_follows_one_hop() {
+        let (wallet, first, second) = (pid(1), pid(2), pid(3));
+        register(wallet, first);
+        // a session key registering a session of its own
+        register(first, second);
+
+        assert_eq!(resolve_caller(second), Some(first));
+        assert!(check::delegated(second).is_ok());
+
+        // so it never gets to act as the wallet
+        assert!(run(is_wallet(wallet)(second)).is_err());
+        assert!(run(is_wallet(wallet)(first)).is_ok());
+    }
+}
@@ -1,38 +1,142 @@
 use crate::{
     Error,
+    cdk::structures::storable::{Bound, Storable},
     config::ConfigError,
     types::{CanisterType, Cycles, TC},
 };
-use candid::Principal;
-use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use thiserror::Error as ThisError;
 
 ///
 /// ConfigDataError
+/// every message starts with the toml path of the bad value
 ///
 
 #[derive(Debug, ThisError)]
 pub enum ConfigDataError {
-    #[error("invalid principal: {0} ({1})")]
-    InvalidPrincipal(String, usize),
+    #[error("{0}: invalid principal '{1}'")]
+    InvalidPrincipal(String, String),
 
-    #[error("canister not found: {0}")]
+    #[error("{0}: {1} can't be a controller")]
+    InvalidController(String, Principal),
+
+    #[error("pool.maximum_size: {1} is below minimum_size {0}")]
+    InvalidPoolSize(u8, u8),
+
+    #[error("canisters.{0}: canister not found")]
     CanisterNotFound(CanisterType),
+
+    #[error("canisters.{0}: canister type names can be at most {1} bytes once encoded")]
+    CanisterTypeTooLong(CanisterType, u32),
+
+    #[error("canisters.{0}.auto_create: there's no wasm for this canister type")]
+    MissingWasm(CanisterType),
+
+    #[error("{0}: {1}")]
+    InvalidCycles(String, String),
+
+    #[error("endpoints.{0}: unknown endpoint")]
+    UnknownEndpoint(String),
+
+    #[error("endpoints.{0}: internal endpoint, its auth can't be configured")]
+    InternalEndpoint(String),
+
+    #[error("endpoints.{0}.auth: empty auth list, use [\"public\"] or disabled = true")]
+    EmptyEndpointAuth(String),
+
+    #[error("{0}: capacity and refill_secs have to be above zero")]
+    InvalidRateLimit(String),
+
+    #[error("app.capabilities: invalid name '{0}', use lowercase letters, digits and _")]
+    InvalidCapability(String),
 }
 
+///
+/// Constants
+///
+
+// the most root hands out in one go (initial_cycles or a topup) unless cycle_budget is set
+pub const DEFAULT_CYCLE_BUDGET: Cycles = Cycles::new(100 * TC);
+
+// the lowest topup threshold we accept, anything below it leaves too little
+// headroom for the topup to land before the canister runs dry
+pub const MIN_TOPUP_THRESHOLD: Cycles = Cycles::new(TC);
+
+///
+/// ENDPOINTS
+/// the built-in endpoints that can be configured in [endpoints]
+///
+
+pub const ENDPOINTS: &[&str] = &[
+    // all canisters
+    "icu_canister_upgrade_children",
+    "icu_role_grant",
+    "icu_role_revoke",
+    "icu_role_list",
+    "icu_delegation_register",
+    "icu_audit_log",
+    "icu_audit_denials",
+    "icu_config",
+    // root
+    "icu_app",
+    "icu_whitelist",
+    "icu_feature_flag",
+    "icu_config_update",
+    "icu_config_hashes",
+    "icu_canister_status",
+    "icu_adopt_canister",
+    "icu_reparent_canister",
+    "icu_clone_canister",
+    "icu_canister_command",
+    "icu_create_pool_canister",
+    "icu_move_canister_to_pool",
+    "icu_release_pool_canister",
+];
+
+///
+/// INTERNAL_ENDPOINTS
+/// plumbing between canisters, their rules are fixed so [endpoints] can't open them up
+///
+
+pub const INTERNAL_ENDPOINTS: &[&str] = &[
+    "icu_cycles_return",
+    "icu_canister_children_update",
+    "icu_canister_children_reset",
+    "icu_canister_parents_update",
+    "icu_state_update",
+    "icu_state_cascade",
+    "icu_response",
+];
+
 ///
 /// ConfigData
+/// only ever deserialized from toml, CandidType is for the icu_config view
 ///
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct ConfigData {
     // controllers
     // a vec because we just append it to the controller arguments
     #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
     pub controllers: Vec<Principal>,
 
+    // cycle_budget
+    // the most root will spend on one canister at a time, see DEFAULT_CYCLE_BUDGET
+    #[serde(default, deserialize_with = "optional_cycles")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(schema_with = "super::schema::optional_cycles")
+    )]
+    pub cycle_budget: Option<Cycles>,
+
+    #[serde(default)]
+    pub app: App,
+
     #[serde(default)]
     pub canisters: HashMap<CanisterType, Canister>,
 
@@ -47,15 +151,62 @@ pub struct ConfigData {
 
     #[serde(default)]
     pub cycle_tracker: bool,
+
+    #[serde(default)]
+    pub endpoints: HashMap<String, Endpoint>,
+
+    #[serde(default)]
+    pub inspect: Inspect,
+
+    #[serde(default)]
+    pub rate_limit: RateLimits,
 }
 
 impl ConfigData {
     pub(super) fn validate(&self) -> Result<(), ConfigDataError> {
+        self.validate_principals()?;
+        self.validate_canisters()?;
+        self.validate_pool()?;
+        self.validate_endpoints()?;
+        self.validate_rate_limits()?;
+        self.validate_app()?;
+
+        Ok(())
+    }
+
+    ///
+    /// validate_wasms
+    /// root checks this when it starts, the wasms aren't known when icu_build! runs
+    ///
+    pub fn validate_wasms(&self, wasms: &[CanisterType]) -> Result<(), ConfigDataError> {
+        for (ty, canister) in &self.canisters {
+            if canister.auto_create.is_some_and(|n| n > 0) && !wasms.contains(ty) {
+                return Err(ConfigDataError::MissingWasm(ty.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // validate_principals
+    fn validate_principals(&self) -> Result<(), ConfigDataError> {
+        for (i, pid) in self.controllers.iter().enumerate() {
+            if *pid == Principal::anonymous() || *pid == Principal::management_canister() {
+                return Err(ConfigDataError::InvalidController(
+                    format!("controllers[{i}]"),
+                    *pid,
+                ));
+            }
+        }
+
         if let Some(list) = &self.whitelist {
-            for (i, s) in list.principals.iter().enumerate() {
+            for s in &list.principals {
                 // Reject if invalid principal format
                 if Principal::from_text(s).is_err() {
-                    return Err(ConfigDataError::InvalidPrincipal(s.to_string(), i));
+                    return Err(ConfigDataError::InvalidPrincipal(
+                        "whitelist.principals".to_string(),
+                        s.clone(),
+                    ));
                 }
             }
         }
@@ -63,6 +214,136 @@ impl ConfigData {
         Ok(())
     }
 
+    // validate_canisters
+    fn validate_canisters(&self) -> Result<(), ConfigDataError> {
+        let Bound::Bounded { max_size, .. } = CanisterType::BOUND else {
+            unreachable!("CanisterType is bounded");
+        };
+
+        for (ty, canister) in &self.canisters {
+            if ty.to_bytes().len() > max_size as usize {
+                return Err(ConfigDataError::CanisterTypeTooLong(ty.clone(), max_size));
+            }
+
+            let path = format!("canisters.{ty}");
+            self.check_budget(&format!("{path}.initial_cycles"), canister.initial_cycles)?;
+            if let Some(topup) = &canister.topup {
+                self.check_topup(&format!("{path}.topup"), topup)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // validate_pool
+    fn validate_pool(&self) -> Result<(), ConfigDataError> {
+        if let Some(max) = self.pool.maximum_size
+            && max < self.pool.minimum_size
+        {
+            return Err(ConfigDataError::InvalidPoolSize(
+                self.pool.minimum_size,
+                max,
+            ));
+        }
+
+        if let Some(topup) = &self.pool.topup {
+            self.check_topup("pool.topup", topup)?;
+        }
+
+        Ok(())
+    }
+
+    // validate_endpoints
+    fn validate_endpoints(&self) -> Result<(), ConfigDataError> {
+        for (name, endpoint) in &self.endpoints {
+            if INTERNAL_ENDPOINTS.contains(&name.as_str()) {
+                return Err(ConfigDataError::InternalEndpoint(name.clone()));
+            }
+            if !ENDPOINTS.contains(&name.as_str()) {
+                return Err(ConfigDataError::UnknownEndpoint(name.clone()));
+            }
+            if endpoint.auth.as_ref().is_some_and(Vec::is_empty) {
+                return Err(ConfigDataError::EmptyEndpointAuth(name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // validate_rate_limits
+    fn validate_rate_limits(&self) -> Result<(), ConfigDataError> {
+        let default = self
+            .rate_limit
+            .default
+            .iter()
+            .map(|l| ("rate_limit.default".to_string(), l));
+        let methods = self
+            .rate_limit
+            .methods
+            .iter()
+            .map(|(k, l)| (format!("rate_limit.methods.{k}"), l));
+
+        for (path, limit) in default.chain(methods) {
+            if limit.capacity == 0 || limit.refill_secs == 0 {
+                return Err(ConfigDataError::InvalidRateLimit(path));
+            }
+        }
+
+        Ok(())
+    }
+
+    // validate_app
+    fn validate_app(&self) -> Result<(), ConfigDataError> {
+        for name in &self.app.capabilities {
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+            if !valid {
+                return Err(ConfigDataError::InvalidCapability(name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // check_topup
+    fn check_topup(&self, path: &str, topup: &CanisterTopup) -> Result<(), ConfigDataError> {
+        if topup.amount == Cycles::default() {
+            return Err(ConfigDataError::InvalidCycles(
+                format!("{path}.amount"),
+                "has to be above zero".to_string(),
+            ));
+        }
+        self.check_budget(&format!("{path}.amount"), topup.amount)?;
+
+        if topup.threshold < MIN_TOPUP_THRESHOLD {
+            return Err(ConfigDataError::InvalidCycles(
+                format!("{path}.threshold"),
+                format!(
+                    "{} is below the minimum topup threshold ({MIN_TOPUP_THRESHOLD})",
+                    topup.threshold
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // check_budget
+    fn check_budget(&self, path: &str, cycles: Cycles) -> Result<(), ConfigDataError> {
+        let budget = self.cycle_budget.unwrap_or(DEFAULT_CYCLE_BUDGET);
+
+        if cycles > budget {
+            return Err(ConfigDataError::InvalidCycles(
+                path.to_string(),
+                format!("{cycles} is above the cycle budget of {budget}"),
+            ));
+        }
+
+        Ok(())
+    }
+
     /// Lookup a canister config by type name (string).
     pub fn get_canister(&self, ty: &CanisterType) -> Result<Canister, Error> {
         self.canisters.get(ty).cloned().ok_or_else(|| {
@@ -71,14 +352,38 @@ impl ConfigData {
     }
 }
 
+// optional_cycles
+fn optional_cycles<'de, D>(deserializer: D) -> Result<Option<Cycles>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Cycles::from_config(deserializer).map(Some)
+}
+
+///
+/// App
+/// capabilities : named features that can be switched to their own AppMode, ie. "trading"
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct App {
+    #[serde(default)]
+    pub capabilities: BTreeSet<String>,
+}
+
 ///
 /// Canister
 ///
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub struct Canister {
     pub auto_create: Option<u16>,
     #[serde(deserialize_with = "Cycles::from_config")]
+    #[cfg_attr(feature = "schema", schemars(schema_with = "super::schema::cycles"))]
     pub initial_cycles: Cycles,
     pub topup: Option<CanisterTopup>,
     pub uses_directory: bool,
@@ -90,12 +395,16 @@ pub struct Canister {
 /// auto_topup : default to false
 ///
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(CandidType, Clone, Debug, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub struct CanisterTopup {
     #[serde(deserialize_with = "Cycles::from_config")]
+    #[cfg_attr(feature = "schema", schemars(schema_with = "super::schema::cycles"))]
     pub threshold: Cycles,
 
     #[serde(deserialize_with = "Cycles::from_config")]
+    #[cfg_attr(feature = "schema", schemars(schema_with = "super::schema::cycles"))]
     pub amount: Cycles,
 }
 
@@ -110,19 +419,164 @@ impl Default for CanisterTopup {
 
 ///
 /// CanisterPool
-/// defaults to a minimum size of 0
+/// defaults to a minimum size of 0, with no maximum size
+///
+/// topup : pool canisters below the threshold get topped up by root
 ///
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub struct CanisterPool {
     pub minimum_size: u8,
+
+    #[serde(default)]
+    pub maximum_size: Option<u8>,
+
+    #[serde(default)]
+    pub topup: Option<CanisterTopup>,
+}
+
+///
+/// Endpoint
+/// overrides for a built-in endpoint, anything not listed keeps its default rules
+///
+/// auth     : the caller has to pass any one of these
+/// disabled : the endpoint always rejects
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Endpoint {
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(schema_with = "super::schema::auth_rules")
+    )]
+    pub auth: Option<Vec<AuthRule>>,
+
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+///
+/// AuthRule
+/// written as a string in the config, roles are "role:name"
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub enum AuthRule {
+    Public,
+    App,
+    Child,
+    Controller,
+    Parent,
+    Root,
+    SameCanister,
+    Whitelisted,
+    Role(String),
+}
+
+impl TryFrom<String> for AuthRule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let rule = match s.as_str() {
+            "public" => Self::Public,
+            "app" => Self::App,
+            "child" => Self::Child,
+            "controller" => Self::Controller,
+            "parent" => Self::Parent,
+            "root" => Self::Root,
+            "same_canister" => Self::SameCanister,
+            "whitelisted" => Self::Whitelisted,
+            _ => match s.strip_prefix("role:") {
+                Some(role) if !role.is_empty() => Self::Role(role.to_string()),
+                _ => return Err(format!("unknown auth rule '{s}'")),
+            },
+        };
+
+        Ok(rule)
+    }
+}
+
+///
+/// Inspect
+/// rules for the generated canister_inspect_message (see icu_inspect_message!)
+///
+/// allow_anonymous : let the anonymous principal through, defaults to false
+/// max_payload     : argument size limit in bytes for every method
+/// methods         : per-method overrides of max_payload
+/// controller_only : methods that only controllers can call via ingress
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Inspect {
+    #[serde(default)]
+    pub allow_anonymous: bool,
+
+    #[serde(default)]
+    pub max_payload: Option<u64>,
+
+    #[serde(default)]
+    pub methods: HashMap<String, u64>,
+
+    #[serde(default)]
+    pub controller_only: HashSet<String>,
+}
+
+impl Inspect {
+    #[must_use]
+    pub fn max_payload(&self, method: &str) -> Option<u64> {
+        self.methods.get(method).copied().or(self.max_payload)
+    }
+}
+
+///
+/// RateLimits
+///
+/// default    : applies to any method that calls the rate_limit rule without its own entry
+/// per_method : the default gives each method its own bucket instead of sharing one
+/// methods    : per-method limits, always a bucket per method
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct RateLimits {
+    #[serde(default)]
+    pub default: Option<RateLimit>,
+
+    #[serde(default)]
+    pub per_method: bool,
+
+    #[serde(default)]
+    pub methods: HashMap<String, RateLimit>,
+}
+
+///
+/// RateLimit
+/// a token bucket, capacity is the burst and one call is earned back every refill_secs
+///
+
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub refill_secs: u64,
 }
 
 ///
 /// Whitelist
 ///
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct WhiteList {
     // principals
@@ -136,9 +590,117 @@ pub struct WhiteList {
 /// Standards
 ///
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct Standards {
     #[serde(default)]
     pub icrc21: bool,
 }
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+        [canisters.test]
+        auto_create = 1
+        initial_cycles = "5T"
+        uses_directory = false
+        topup.threshold = "2T"
+        topup.amount = "3T"
+    "#;
+
+    fn validate(extra: &str) -> Result<(), String> {
+        let config: ConfigData =
+            toml::from_str(&format!("{BASE}\n{extra}")).map_err(|e| e.to_string())?;
+
+        config.validate().map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn errors_show_the_toml_path() {
+        assert!(validate("").is_ok());
+
+        let err =
+            validate("[pool]\nminimum_size = 1\ntopup.threshold = \"2T\"\ntopup.amount = \"0T\"")
+                .unwrap_err();
+        assert_eq!(err, "pool.topup.amount: has to be above zero");
+
+        let err =
+            validate("[pool]\nminimum_size = 1\ntopup.threshold = \"100B\"\ntopup.amount = \"1T\"")
+                .unwrap_err();
+        assert!(err.starts_with("pool.topup.threshold: "), "{err}");
+
+        let err = validate("[whitelist]\nprincipals = [\"nope\"]").unwrap_err();
+        assert_eq!(err, "whitelist.principals: invalid principal 'nope'");
+
+        let err = validate("[rate_limit.methods.foo]\ncapacity = 0\nrefill_secs = 1").unwrap_err();
+        assert!(err.starts_with("rate_limit.methods.foo: "), "{err}");
+    }
+
+    #[test]
+    fn cycles_stay_within_the_budget() {
+        let err = validate("[canisters.big]\ninitial_cycles = \"200T\"\nuses_directory = false")
+            .unwrap_err();
+        assert!(err.starts_with("canisters.big.initial_cycles: "), "{err}");
+
+        // raising the budget allows it
+        let config = format!("cycle_budget = \"500T\"\n{BASE}");
+        let config: ConfigData = toml::from_str(&format!(
+            "{config}\n[canisters.big]\ninitial_cycles = \"200T\"\nuses_directory = false"
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn bad_controllers_and_names() {
+        let config: ConfigData =
+            toml::from_str(&format!("controllers = [\"2vxsx-fae\"]\n{BASE}")).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigDataError::InvalidController(..))
+        ));
+
+        let long = "x".repeat(60);
+        assert!(
+            validate(&format!(
+                "[canisters.{long}]\ninitial_cycles = \"1T\"\nuses_directory = false"
+            ))
+            .unwrap_err()
+            .contains("at most")
+        );
+
+        // unknown standards are rejected by serde
+        assert!(validate("[standards]\nicrc99 = true").is_err());
+    }
+
+    #[test]
+    fn internal_endpoints_cant_be_configured() {
+        for name in INTERNAL_ENDPOINTS {
+            let err = validate(&format!("[endpoints.{name}]\nauth = [\"public\"]")).unwrap_err();
+            assert_eq!(
+                err,
+                format!("endpoints.{name}: internal endpoint, its auth can't be configured")
+            );
+        }
+
+        assert!(validate("[endpoints.icu_app]\nauth = [\"role:ops\"]").is_ok());
+    }
+
+    #[test]
+    fn auto_create_needs_a_wasm() {
+        let config: ConfigData = toml::from_str(BASE).unwrap();
+
+        assert!(config.validate_wasms(&[CanisterType::new("test")]).is_ok());
+        assert!(matches!(
+            config.validate_wasms(&[]),
+            Err(ConfigDataError::MissingWasm(_))
+        ));
+    }
+}
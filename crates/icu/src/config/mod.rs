@@ -1,11 +1,17 @@
 mod data;
+#[cfg(feature = "schema")]
+pub mod schema;
 
-use crate::{Error, types::CanisterType};
+use crate::{Error, memory::ConfigOverlayData, types::CanisterType};
+use candid::CandidType;
 use data::{Canister, ConfigDataError};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::{cell::RefCell, sync::Arc};
 use thiserror::Error as ThisError;
+use toml::Table;
 
-pub use data::ConfigData;
+pub use data::{App, AuthRule, ConfigData, Endpoint, Inspect, RateLimit, RateLimits};
 
 //
 // CONFIG
@@ -24,6 +30,18 @@ pub use data::ConfigData;
 
 thread_local! {
     static CONFIG: RefCell<Option<Arc<ConfigData>>> = const {  RefCell::new(None) };
+
+    // the toml baked into the wasm, runtime overlays go on top of it
+    static BASE_TOML: RefCell<Option<String>> = const { RefCell::new(None) };
+
+    // the [profile.x] picked by icu_build!, if any
+    static PROFILE: RefCell<Option<String>> = const { RefCell::new(None) };
+
+    // sha256 of the config in use, set whenever CONFIG is
+    static HASH: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+
+    // where the config in use came from, set whenever CONFIG is
+    static SOURCE: RefCell<ConfigSource> = const { RefCell::new(ConfigSource::BakedIn) };
 }
 
 ///
@@ -41,10 +59,58 @@ pub enum ConfigError {
     #[error("config has not been initialized")]
     NotInitialized,
 
+    #[error("profile {0} not found, add a [profile.{0}] table to the config")]
+    ProfileNotFound(String),
+
+    #[error("[profile.{0}] has to be a table")]
+    InvalidProfile(String),
+
+    #[error("the config can't disable icu_config_update, it would be locked until an upgrade")]
+    WouldLockOut,
+
     #[error(transparent)]
     ConfigDataError(#[from] ConfigDataError),
 }
 
+///
+/// ConfigSource
+///
+/// BakedIn  : the icu.toml (and profile) the wasm was built with
+/// Patched  : a runtime overlay merged over the baked-in config
+/// Replaced : a runtime overlay used instead of the baked-in config
+///
+
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum ConfigSource {
+    BakedIn,
+    Patched,
+    Replaced,
+}
+
+impl From<&ConfigOverlayData> for ConfigSource {
+    fn from(overlay: &ConfigOverlayData) -> Self {
+        match overlay {
+            ConfigOverlayData { toml: None, .. } => Self::BakedIn,
+            ConfigOverlayData { replace: false, .. } => Self::Patched,
+            ConfigOverlayData { replace: true, .. } => Self::Replaced,
+        }
+    }
+}
+
+///
+/// ConfigView
+/// what icu_config returns, the hash is the same on every canister running the
+/// same config, whatever order the toml was written in
+///
+
+#[derive(CandidType, Clone, Debug)]
+pub struct ConfigView {
+    pub source: ConfigSource,
+    pub profile: Option<String>,
+    pub hash: Vec<u8>,
+    pub config: Arc<ConfigData>,
+}
+
 ///
 /// Config
 ///
@@ -66,21 +132,122 @@ impl Config {
 
     /// Initialize the global configuration from a TOML string.
     pub fn init_from_toml(config_str: &str) -> Result<(), Error> {
-        let config: ConfigData =
-            toml::from_str(config_str).map_err(|e| ConfigError::CannotParseToml(e.to_string()))?;
+        Self::init_with_profile(config_str, None)
+    }
 
-        // validate
-        config.validate().map_err(ConfigError::from)?;
+    ///
+    /// init_with_profile
+    /// the base config with [profile.name] merged over it
+    ///
+    pub fn init_with_profile(config_str: &str, profile: Option<&str>) -> Result<(), Error> {
+        let table = build_table(config_str, profile, &ConfigOverlayData::default())?;
+        let config = parse_config(table.clone())?;
 
         CONFIG.with(|cfg| {
             let mut borrow = cfg.borrow_mut();
             if borrow.is_some() {
-                return Err(ConfigError::AlreadyInitialized.into());
+                return Err(ConfigError::AlreadyInitialized);
             }
             *borrow = Some(Arc::new(config));
 
             Ok(())
-        })
+        })?;
+
+        HASH.with_borrow_mut(|hash| *hash = hash_table(&table));
+        BASE_TOML.with_borrow_mut(|base| *base = Some(config_str.to_string()));
+        PROFILE.with_borrow_mut(|p| *p = profile.map(ToString::to_string));
+
+        Ok(())
+    }
+
+    // profile
+    // the profile this canister was built with
+    #[must_use]
+    pub fn profile() -> Option<String> {
+        PROFILE.with_borrow(Clone::clone)
+    }
+
+    // hash
+    // sha256 of the config in use, empty before it's initialized
+    #[must_use]
+    pub fn hash() -> Vec<u8> {
+        HASH.with_borrow(Clone::clone)
+    }
+
+    // source
+    // the overlay that's actually applied, not whatever is stored
+    #[must_use]
+    pub fn source() -> ConfigSource {
+        SOURCE.with_borrow(|source| *source)
+    }
+
+    ///
+    /// profile_names
+    /// every [profile.x] in a config
+    ///
+    pub fn profile_names(config_str: &str) -> Result<Vec<String>, Error> {
+        let table = parse_table(config_str)?;
+
+        let names = match table.get("profile") {
+            Some(toml::Value::Table(profiles)) => profiles.keys().cloned().collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(names)
+    }
+
+    ///
+    /// check_profile
+    /// builds and validates a profile without initializing anything
+    ///
+    pub fn check_profile(config_str: &str, profile: &str) -> Result<(), Error> {
+        build_config(config_str, Some(profile), &ConfigOverlayData::default())?;
+
+        Ok(())
+    }
+
+    ///
+    /// build
+    /// the baked-in config with an overlay on top, parsed and validated but not applied
+    ///
+    pub fn build(overlay: &ConfigOverlayData) -> Result<ConfigData, Error> {
+        let base = BASE_TOML
+            .with_borrow(Clone::clone)
+            .ok_or(ConfigError::NotInitialized)?;
+
+        let profile = Self::profile();
+
+        Ok(build_config(&base, profile.as_deref(), overlay)?)
+    }
+
+    ///
+    /// apply
+    /// swaps the config for the baked-in one with this overlay, the next
+    /// Config::try_get picks it up
+    ///
+    pub fn apply(overlay: &ConfigOverlayData) -> Result<(), Error> {
+        let base = BASE_TOML
+            .with_borrow(Clone::clone)
+            .ok_or(ConfigError::NotInitialized)?;
+        let table = build_table(&base, Self::profile().as_deref(), overlay)?;
+        let config = parse_config(table.clone())?;
+
+        CONFIG.with_borrow_mut(|cfg| *cfg = Some(Arc::new(config)));
+        HASH.with_borrow_mut(|hash| *hash = hash_table(&table));
+        SOURCE.with_borrow_mut(|source| *source = ConfigSource::from(overlay));
+
+        Ok(())
+    }
+
+    ///
+    /// merge_patch
+    /// merges a toml patch into an existing overlay, returning the new overlay
+    ///
+    pub fn merge_patch(overlay: Option<&str>, patch: &str) -> Result<String, Error> {
+        let mut table = parse_table(overlay.unwrap_or_default())?;
+        merge_tables(&mut table, parse_table(patch)?);
+
+        Ok(table.to_string())
     }
 
     pub fn try_get_canister(ty: &CanisterType) -> Result<Canister, Error> {
@@ -88,3 +255,231 @@ impl Config {
         cfg.get_canister(ty)
     }
 }
+
+// build_config
+fn build_config(
+    base: &str,
+    profile: Option<&str>,
+    overlay: &ConfigOverlayData,
+) -> Result<ConfigData, ConfigError> {
+    parse_config(build_table(base, profile, overlay)?)
+}
+
+// build_table
+// base, then the profile, then the runtime overlay
+fn build_table(
+    base: &str,
+    profile: Option<&str>,
+    overlay: &ConfigOverlayData,
+) -> Result<Table, ConfigError> {
+    let mut table = if overlay.replace {
+        Table::new()
+    } else {
+        base_table(base, profile)?
+    };
+    if let Some(toml) = &overlay.toml {
+        merge_tables(&mut table, parse_table(toml)?);
+    }
+
+    Ok(table)
+}
+
+// hash_table
+// toml::Table is sorted, so the same config always prints the same way
+fn hash_table(table: &Table) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(table.to_string());
+
+    hasher.finalize().to_vec()
+}
+
+// parse_config
+fn parse_config(table: Table) -> Result<ConfigData, ConfigError> {
+    let config: ConfigData = toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| ConfigError::CannotParseToml(e.to_string()))?;
+    config.validate()?;
+
+    Ok(config)
+}
+
+// base_table
+// takes the [profile] tables out, they aren't part of ConfigData
+fn base_table(base: &str, profile: Option<&str>) -> Result<Table, ConfigError> {
+    let mut table = parse_table(base)?;
+    let mut profiles = match table.remove("profile") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => {
+            return Err(ConfigError::CannotParseToml(
+                "[profile] has to be a table of profiles".to_string(),
+            ));
+        }
+        None => Table::new(),
+    };
+
+    if let Some(name) = profile {
+        match profiles.remove(name) {
+            Some(toml::Value::Table(patch)) => merge_tables(&mut table, patch),
+            Some(_) => return Err(ConfigError::InvalidProfile(name.to_string())),
+            None => return Err(ConfigError::ProfileNotFound(name.to_string())),
+        }
+    }
+
+    Ok(table)
+}
+
+// parse_table
+fn parse_table(s: &str) -> Result<Table, ConfigError> {
+    toml::from_str(s).map_err(|e| ConfigError::CannotParseToml(e.to_string()))
+}
+
+// merge_tables
+// tables merge key by key, anything else (arrays included) is replaced
+fn merge_tables(base: &mut Table, patch: Table) {
+    for (key, value) in patch {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(patch)) => {
+                merge_tables(existing, patch);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+        [pool]
+        minimum_size = 2
+        topup.threshold = "2T"
+        topup.amount = "3T"
+
+        [canisters.test]
+        initial_cycles = "5T"
+        uses_directory = false
+
+        [profile.staging]
+        pool.minimum_size = 1
+        canisters.test.initial_cycles = "1T"
+
+        [profile.broken]
+        pool.maximum_size = 1
+    "#;
+
+    fn overlay(toml: &str, replace: bool) -> ConfigOverlayData {
+        ConfigOverlayData {
+            toml: Some(toml.to_string()),
+            replace,
+        }
+    }
+
+    #[test]
+    fn patch_merges_over_the_base() {
+        let config = build_config(BASE, None, &overlay("pool.maximum_size = 8", false)).unwrap();
+
+        assert_eq!(config.pool.minimum_size, 2);
+        assert_eq!(config.pool.maximum_size, Some(8));
+        assert!(config.canisters.contains_key(&CanisterType::new("test")));
+    }
+
+    #[test]
+    fn replace_ignores_the_base() {
+        let config = build_config(BASE, None, &overlay("pool.minimum_size = 1", true)).unwrap();
+
+        assert_eq!(config.pool.minimum_size, 1);
+        assert!(config.canisters.is_empty());
+    }
+
+    #[test]
+    fn overlays_are_validated() {
+        // maximum below minimum
+        assert!(build_config(BASE, None, &overlay("pool.maximum_size = 1", false)).is_err());
+        assert!(build_config(BASE, None, &overlay("nonsense = true", false)).is_err());
+        assert!(build_config(BASE, None, &overlay("not toml", false)).is_err());
+    }
+
+    #[test]
+    fn patches_stack() {
+        let first = Config::merge_patch(None, "[pool]\nmaximum_size = 8").unwrap();
+        let second = Config::merge_patch(Some(&first), "[pool]\nminimum_size = 4").unwrap();
+
+        let config = build_config(BASE, None, &overlay(&second, false)).unwrap();
+        assert_eq!(config.pool.minimum_size, 4);
+        assert_eq!(config.pool.maximum_size, Some(8));
+    }
+
+    #[test]
+    fn profiles_override_the_base() {
+        let empty = ConfigOverlayData::default();
+
+        let base = build_config(BASE, None, &empty).unwrap();
+        assert_eq!(base.pool.minimum_size, 2);
+
+        let staging = build_config(BASE, Some("staging"), &empty).unwrap();
+        assert_eq!(staging.pool.minimum_size, 1);
+        assert!(staging.pool.topup.is_some());
+
+        assert!(matches!(
+            build_config(BASE, Some("mainnet"), &empty),
+            Err(ConfigError::ProfileNotFound(_))
+        ));
+        assert!(build_config(BASE, Some("broken"), &empty).is_err());
+
+        let mut names = Config::profile_names(BASE).unwrap();
+        names.sort();
+        assert_eq!(names, ["broken", "staging"]);
+    }
+
+    #[test]
+    fn hash_ignores_toml_order() {
+        let none = ConfigOverlayData::default();
+        let reordered = r#"
+            [canisters.test]
+            uses_directory = false
+            initial_cycles = "5T"
+
+            [pool]
+            topup.amount = "3T"
+            topup.threshold = "2T"
+            minimum_size = 2
+        "#;
+
+        let a = hash_table(&build_table(BASE, None, &none).unwrap());
+        let b = hash_table(&build_table(reordered, None, &none).unwrap());
+        let c = hash_table(&build_table(BASE, Some("staging"), &none).unwrap());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        assert_eq!(ConfigSource::from(&none), ConfigSource::BakedIn);
+        assert_eq!(
+            ConfigSource::from(&overlay("", true)),
+            ConfigSource::Replaced
+        );
+    }
+
+    #[test]
+    fn source_follows_the_applied_overlay() {
+        Config::init_from_toml(BASE).unwrap();
+        assert_eq!(Config::source(), ConfigSource::BakedIn);
+
+        Config::apply(&overlay("pool.maximum_size = 8", false)).unwrap();
+        assert_eq!(Config::source(), ConfigSource::Patched);
+        let hash = Config::hash();
+
+        // a bad overlay changes nothing
+        assert!(Config::apply(&overlay("pool.maximum_size = 1", false)).is_err());
+        assert_eq!(Config::source(), ConfigSource::Patched);
+        assert_eq!(Config::hash(), hash);
+
+        Config::apply(&ConfigOverlayData::default()).unwrap();
+        assert_eq!(Config::source(), ConfigSource::BakedIn);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::{ConfigData, data::ENDPOINTS};
+use schemars::{Schema, SchemaGenerator, json_schema, schema_for};
+use serde_json::json;
+
+///
+/// json_schema
+/// the JSON Schema for icu.toml, editors pick it up with a `#:schema ./icu.schema.json`
+/// comment at the top of the file.  Print it with
+/// cargo run -p icu --example config_schema --features schema
+///
+#[must_use]
+pub fn json_schema() -> Schema {
+    let mut schema = schema_for!(ConfigData);
+    schema.insert("title".to_string(), json!("icu.toml"));
+    schema.insert(
+        "description".to_string(),
+        json!("config for every canister in an icu app, baked in at build time by icu_build!"),
+    );
+
+    if let Some(properties) = schema
+        .get_mut("properties")
+        .and_then(serde_json::Value::as_object_mut)
+    {
+        // only the built-in endpoints can be configured
+        if let Some(endpoints) = properties.get_mut("endpoints") {
+            endpoints["propertyNames"] = json!({ "enum": ENDPOINTS });
+        }
+
+        // [profile.x] tables are merged over the base before ConfigData sees them,
+        // they're partial so only the table names are checked
+        properties.insert(
+            "profile".to_string(),
+            json!({
+                "description": "named overrides merged over the base config, picked with ICU_PROFILE or DFX_NETWORK",
+                "type": "object",
+                "additionalProperties": { "type": "object" },
+            }),
+        );
+    }
+
+    schema
+}
+
+// cycles
+// Cycles::from_config takes a number, or a string with an optional K/M/B/T/Q suffix
+pub(super) fn cycles(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "description": "cycles, as a number or a string like \"10T\" or \"2.5B\" (K, M, B, T, Q)",
+        "anyOf": [
+            { "type": "integer", "minimum": 0 },
+            { "type": "string", "pattern": "^([0-9]+\\.?[0-9]*|\\.[0-9]+)[KMBTQ]?$" },
+        ],
+    })
+}
+
+// optional_cycles
+pub(super) fn optional_cycles(generator: &mut SchemaGenerator) -> Schema {
+    cycles(generator)
+}
+
+// AUTH_RULES
+// the fixed AuthRule strings, roles are matched by pattern
+const AUTH_RULES: &[&str] = &[
+    "public",
+    "app",
+    "child",
+    "controller",
+    "parent",
+    "root",
+    "same_canister",
+    "whitelisted",
+];
+
+// auth_rules
+pub(super) fn auth_rules(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "array",
+        "items": {
+            "anyOf": [
+                { "enum": AUTH_RULES },
+                { "type": "string", "pattern": "^role:.+$" },
+            ],
+        },
+    })
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthRule;
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let schema = json_schema();
+        let defs = &schema.as_value()["$defs"];
+
+        assert_eq!(schema.as_value()["additionalProperties"], false);
+        for name in [
+            "Canister",
+            "CanisterTopup",
+            "CanisterPool",
+            "Endpoint",
+            "Inspect",
+        ] {
+            assert_eq!(defs[name]["additionalProperties"], false, "{name}");
+        }
+        assert!(schema.as_value()["properties"]["profile"].is_object());
+    }
+
+    #[test]
+    fn auth_rules_parse() {
+        let mut rules = Vec::new();
+        for name in AUTH_RULES {
+            let rule = AuthRule::try_from(name.to_string()).unwrap();
+
+            // fails to build when a variant is added, so it gets added here too
+            match rule {
+                AuthRule::Public
+                | AuthRule::App
+                | AuthRule::Child
+                | AuthRule::Controller
+                | AuthRule::Parent
+                | AuthRule::Root
+                | AuthRule::SameCanister
+                | AuthRule::Whitelisted => {}
+                AuthRule::Role(_) => panic!("{name} parsed as a role"),
+            }
+            assert!(!rules.contains(&rule), "{name} is listed twice");
+            rules.push(rule);
+        }
+        assert_eq!(rules.len(), 8);
+
+        // and the pattern lines up with the role prefix
+        assert_eq!(
+            AuthRule::try_from("role:admin".to_string()),
+            Ok(AuthRule::Role("admin".to_string()))
+        );
+        assert!(AuthRule::try_from("role:".to_string()).is_err());
+    }
+
+    #[test]
+    fn committed_schema_is_current() {
+        let committed: serde_json::Value =
+            serde_json::from_str(include_str!("../../../canisters/icu.schema.json")).unwrap();
+
+        assert!(
+            committed == *json_schema().as_value(),
+            "crates/canisters/icu.schema.json is out of date, regenerate it with \
+             cargo run -p icu --example config_schema --features schema"
+        );
+    }
+}
@@ -1,7 +1,12 @@
 use crate::{
-    cdk::api::{is_controller, msg_caller},
-    memory::{AppState, app_state::AppMode},
+    cdk::api::{canister_self, is_controller, msg_arg_data, msg_caller, msg_method_name},
+    config::{Config, Inspect},
+    memory::{
+        AppState, CanisterState,
+        app_state::{AppMode, Maintenance},
+    },
 };
+use candid::Principal;
 use thiserror::Error as ThisError;
 
 ///
@@ -17,6 +22,27 @@ pub enum GuardError {
 
     #[error("app is readonly")]
     AppReadonly,
+
+    #[error("app is in {0} mode for maintenance: {1}")]
+    Maintenance(AppMode, String),
+
+    #[error("capability {0} is disabled")]
+    CapabilityDisabled(String),
+
+    #[error("capability {0} is readonly")]
+    CapabilityReadonly(String),
+
+    #[error("capability {0} is not declared in [app] capabilities")]
+    UnknownCapability(String),
+
+    #[error("anonymous callers are not allowed")]
+    Anonymous,
+
+    #[error("method {0} can only be called by a controller")]
+    ControllerOnly(String),
+
+    #[error("payload for {0} is {1} bytes, the limit is {2}")]
+    PayloadTooLarge(String, u64, u64),
 }
 
 // guard_query
@@ -25,10 +51,7 @@ pub fn guard_query() -> Result<(), String> {
         return Ok(());
     }
 
-    match AppState::get_mode() {
-        AppMode::Enabled | AppMode::Readonly => Ok(()),
-        AppMode::Disabled => Err(GuardError::AppDisabled.to_string()),
-    }
+    check_query(app_mode(), AppState::get_maintenance().as_ref()).map_err(|e| e.to_string())
 }
 
 // guard_update
@@ -37,9 +60,258 @@ pub fn guard_update() -> Result<(), String> {
         return Ok(());
     }
 
-    match AppState::get_mode() {
+    check_update(app_mode(), AppState::get_maintenance().as_ref()).map_err(|e| e.to_string())
+}
+
+// guard_query_capability
+// like guard_query, but uses the mode of one capability
+pub fn guard_query_capability(name: &str) -> Result<(), String> {
+    if is_controller(&msg_caller()) {
+        return Ok(());
+    }
+
+    if let Some(maintenance) = AppState::get_maintenance() {
+        check_query(app_mode(), Some(&maintenance)).map_err(|e| e.to_string())?;
+    }
+
+    match capability_mode(name)? {
+        AppMode::Enabled | AppMode::Readonly => Ok(()),
+        AppMode::Disabled => Err(GuardError::CapabilityDisabled(name.to_string()).to_string()),
+    }
+}
+
+// guard_update_capability
+pub fn guard_update_capability(name: &str) -> Result<(), String> {
+    if is_controller(&msg_caller()) {
+        return Ok(());
+    }
+
+    if let Some(maintenance) = AppState::get_maintenance() {
+        check_update(app_mode(), Some(&maintenance)).map_err(|e| e.to_string())?;
+    }
+
+    match capability_mode(name)? {
+        AppMode::Enabled => Ok(()),
+        AppMode::Readonly => Err(GuardError::CapabilityReadonly(name.to_string()).to_string()),
+        AppMode::Disabled => Err(GuardError::CapabilityDisabled(name.to_string()).to_string()),
+    }
+}
+
+// check_query
+fn check_query(mode: AppMode, maintenance: Option<&Maintenance>) -> Result<(), GuardError> {
+    match mode {
+        AppMode::Enabled | AppMode::Readonly => Ok(()),
+        AppMode::Disabled => Err(mode_error(mode, maintenance)),
+    }
+}
+
+// check_update
+fn check_update(mode: AppMode, maintenance: Option<&Maintenance>) -> Result<(), GuardError> {
+    match mode {
         AppMode::Enabled => Ok(()),
-        AppMode::Readonly => Err(GuardError::AppReadonly.to_string()),
-        AppMode::Disabled => Err(GuardError::AppDisabled.to_string()),
+        AppMode::Readonly | AppMode::Disabled => Err(mode_error(mode, maintenance)),
+    }
+}
+
+// mode_error
+// during a maintenance window callers get told why and for how long
+fn mode_error(mode: AppMode, maintenance: Option<&Maintenance>) -> GuardError {
+    match (maintenance, mode) {
+        (Some(m), _) => GuardError::Maintenance(mode, m.notice()),
+        (None, AppMode::Readonly) => GuardError::AppReadonly,
+        (None, _) => GuardError::AppDisabled,
+    }
+}
+
+// capability_mode
+// a typo in a capability name shouldn't quietly fall back to the app mode
+fn capability_mode(name: &str) -> Result<AppMode, String> {
+    let declared = Config::try_get().is_ok_and(|config| config.app.capabilities.contains(name));
+    if !declared {
+        return Err(GuardError::UnknownCapability(name.to_string()).to_string());
+    }
+
+    Ok(AppState::get_capability_mode(name).restrict(app_mode()))
+}
+
+// app_mode
+// the app mode, restricted by this canister's type override and by root
+// pinning it (ie. while it's drained)
+fn app_mode() -> AppMode {
+    AppState::get_canister_mode(canister_self(), CanisterState::get_type().as_ref())
+}
+
+// guard_inspect
+// runs inside canister_inspect_message, so only for ingress calls.  The ICU
+// rules run first, then the application's own rule for the method
+pub fn guard_inspect(rule: impl Fn(&str) -> Result<(), String>) -> Result<(), String> {
+    let caller = msg_caller();
+    let method = msg_method_name();
+    let inspect = Config::try_get()
+        .map(|config| config.inspect.clone())
+        .unwrap_or_default();
+
+    check_inspect(
+        &inspect,
+        app_mode(),
+        AppState::get_maintenance().as_ref(),
+        caller,
+        is_controller(&caller),
+        &method,
+        msg_arg_data().len() as u64,
+    )
+    .map_err(|e| e.to_string())?;
+
+    rule(&method)
+}
+
+// check_inspect
+fn check_inspect(
+    inspect: &Inspect,
+    mode: AppMode,
+    maintenance: Option<&Maintenance>,
+    caller: Principal,
+    caller_is_controller: bool,
+    method: &str,
+    payload_len: u64,
+) -> Result<(), GuardError> {
+    if let Some(max) = inspect.max_payload(method)
+        && payload_len > max
+    {
+        return Err(GuardError::PayloadTooLarge(
+            method.to_string(),
+            payload_len,
+            max,
+        ));
+    }
+
+    if caller_is_controller {
+        return Ok(());
+    }
+
+    if caller == Principal::anonymous() && !inspect.allow_anonymous {
+        return Err(GuardError::Anonymous);
+    }
+
+    if inspect.controller_only.contains(method) {
+        return Err(GuardError::ControllerOnly(method.to_string()));
+    }
+
+    // same as guard_update
+    check_update(mode, maintenance)
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    fn inspect() -> Inspect {
+        Inspect {
+            max_payload: Some(100),
+            methods: [("upload".to_string(), 1000)].into(),
+            controller_only: ["icu_app".to_string()].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn payload_limits() {
+        let inspect = inspect();
+
+        assert!(check_inspect(&inspect, AppMode::Enabled, None, user(), false, "foo", 100).is_ok());
+        assert!(matches!(
+            check_inspect(&inspect, AppMode::Enabled, None, user(), false, "foo", 101),
+            Err(GuardError::PayloadTooLarge(..))
+        ));
+        assert!(
+            check_inspect(
+                &inspect,
+                AppMode::Enabled,
+                None,
+                user(),
+                false,
+                "upload",
+                1000
+            )
+            .is_ok()
+        );
+
+        // controllers don't get a bigger payload
+        assert!(check_inspect(&inspect, AppMode::Enabled, None, user(), true, "foo", 101).is_err());
+    }
+
+    #[test]
+    fn callers_and_modes() {
+        let inspect = inspect();
+        let anon = Principal::anonymous();
+
+        assert!(matches!(
+            check_inspect(&inspect, AppMode::Enabled, None, anon, false, "foo", 0),
+            Err(GuardError::Anonymous)
+        ));
+        assert!(matches!(
+            check_inspect(
+                &inspect,
+                AppMode::Enabled,
+                None,
+                user(),
+                false,
+                "icu_app",
+                0
+            ),
+            Err(GuardError::ControllerOnly(_))
+        ));
+        assert!(matches!(
+            check_inspect(&inspect, AppMode::Readonly, None, user(), false, "foo", 0),
+            Err(GuardError::AppReadonly)
+        ));
+
+        // controllers get past everything else
+        assert!(
+            check_inspect(
+                &inspect,
+                AppMode::Disabled,
+                None,
+                user(),
+                true,
+                "icu_app",
+                0
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn maintenance_reason_is_shown() {
+        let window = Maintenance {
+            mode: AppMode::Readonly,
+            start: 0,
+            end: Some(951_782_400),
+            reason: "moving to a new subnet".to_string(),
+            previous: Some(AppMode::Enabled),
+        };
+
+        assert!(check_query(AppMode::Readonly, Some(&window)).is_ok());
+
+        let err = check_update(AppMode::Readonly, Some(&window))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "app is in Readonly mode for maintenance: moving to a new subnet (until 2000-02-29 00:00:00 UTC)"
+        );
+
+        assert!(matches!(
+            check_update(AppMode::Readonly, None),
+            Err(GuardError::AppReadonly)
+        ));
     }
 }
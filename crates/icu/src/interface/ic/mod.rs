@@ -2,19 +2,22 @@ mod canister;
 mod cycles;
 mod helper;
 mod icp;
+mod snapshot;
 mod sns;
 
 pub use canister::*;
 pub use cycles::*;
 pub use helper::*;
 pub use icp::*;
+pub use snapshot::*;
 pub use sns::*;
 
 use crate::{
     Error,
     cdk::mgmt::{
-        self, CanisterInstallMode, CanisterStatusArgs, CanisterStatusResult, DepositCyclesArgs,
-        InstallCodeArgs, UninstallCodeArgs, WasmModule,
+        self, CanisterInstallMode, CanisterStatusArgs, CanisterStatusResult, DeleteCanisterArgs,
+        DepositCyclesArgs, InstallCodeArgs, StartCanisterArgs, StopCanisterArgs, UninstallCodeArgs,
+        WasmModule,
     },
     interface::prelude::*,
 };
@@ -38,6 +41,20 @@ pub fn canister_cycle_balance() -> Cycles {
     crate::cdk::api::canister_cycle_balance().into()
 }
 
+// delete_canister
+// the canister has to be stopped first, any remaining cycles are lost
+pub async fn delete_canister(canister_pid: Principal) -> Result<(), Error> {
+    let args = DeleteCanisterArgs {
+        canister_id: canister_pid,
+    };
+
+    mgmt::delete_canister(&args)
+        .await
+        .map_err(InterfaceError::CallError)?;
+
+    Ok(())
+}
+
 // deposit_cycles
 pub async fn deposit_cycles(canister_pid: Principal, cycles: Cycles) -> Result<(), Error> {
     let args = DepositCyclesArgs {
@@ -94,6 +111,32 @@ pub async fn install_code<T: ArgumentEncoder>(
     Ok(())
 }
 
+// start_canister
+pub async fn start_canister(canister_pid: Principal) -> Result<(), Error> {
+    let args = StartCanisterArgs {
+        canister_id: canister_pid,
+    };
+
+    mgmt::start_canister(&args)
+        .await
+        .map_err(InterfaceError::CallError)?;
+
+    Ok(())
+}
+
+// stop_canister
+pub async fn stop_canister(canister_pid: Principal) -> Result<(), Error> {
+    let args = StopCanisterArgs {
+        canister_id: canister_pid,
+    };
+
+    mgmt::stop_canister(&args)
+        .await
+        .map_err(InterfaceError::CallError)?;
+
+    Ok(())
+}
+
 // uninstall_code
 pub async fn uninstall_code(canister_pid: Principal) -> Result<(), Error> {
     let args = UninstallCodeArgs {
@@ -0,0 +1,161 @@
+use crate::{
+    Error,
+    cdk::{
+        mgmt::{
+            self, DeleteCanisterSnapshotArgs, LoadCanisterSnapshotArgs, Snapshot, SnapshotId,
+            TakeCanisterSnapshotArgs,
+        },
+        mgmt_types::{
+            ReadCanisterSnapshotDataArgs, ReadCanisterSnapshotDataResult,
+            ReadCanisterSnapshotMetadataArgs, ReadCanisterSnapshotMetadataResult, SnapshotDataKind,
+            SnapshotDataOffset, UploadCanisterSnapshotDataArgs, UploadCanisterSnapshotMetadataArgs,
+            UploadCanisterSnapshotMetadataResult,
+        },
+    },
+    interface::prelude::*,
+};
+
+//
+// SNAPSHOTS
+// the read/upload methods aren't wrapped by ic-cdk yet, so we call the
+// management canister directly
+//
+
+// take_canister_snapshot
+pub async fn take_canister_snapshot(canister_pid: Principal) -> Result<Snapshot, Error> {
+    let args = TakeCanisterSnapshotArgs {
+        canister_id: canister_pid,
+        replace_snapshot: None,
+    };
+
+    let snapshot = mgmt::take_canister_snapshot(&args)
+        .await
+        .map_err(InterfaceError::CallError)?;
+
+    Ok(snapshot)
+}
+
+// load_canister_snapshot
+pub async fn load_canister_snapshot(
+    canister_pid: Principal,
+    snapshot_id: SnapshotId,
+) -> Result<(), Error> {
+    let args = LoadCanisterSnapshotArgs {
+        canister_id: canister_pid,
+        snapshot_id,
+    };
+
+    mgmt::load_canister_snapshot(&args)
+        .await
+        .map_err(InterfaceError::CallError)?;
+
+    Ok(())
+}
+
+// delete_canister_snapshot
+pub async fn delete_canister_snapshot(
+    canister_pid: Principal,
+    snapshot_id: SnapshotId,
+) -> Result<(), Error> {
+    let args = DeleteCanisterSnapshotArgs {
+        canister_id: canister_pid,
+        snapshot_id,
+    };
+
+    mgmt::delete_canister_snapshot(&args)
+        .await
+        .map_err(InterfaceError::CallError)?;
+
+    Ok(())
+}
+
+// read_canister_snapshot_metadata
+pub async fn read_canister_snapshot_metadata(
+    canister_pid: Principal,
+    snapshot_id: SnapshotId,
+) -> Result<ReadCanisterSnapshotMetadataResult, Error> {
+    let args = ReadCanisterSnapshotMetadataArgs {
+        canister_id: canister_pid,
+        snapshot_id,
+    };
+
+    let res = Call::unbounded_wait(
+        Principal::management_canister(),
+        "read_canister_snapshot_metadata",
+    )
+    .with_arg(&args)
+    .await
+    .map_err(InterfaceError::from)?
+    .candid()
+    .map_err(InterfaceError::from)?;
+
+    Ok(res)
+}
+
+// read_canister_snapshot_data
+pub async fn read_canister_snapshot_data(
+    canister_pid: Principal,
+    snapshot_id: SnapshotId,
+    kind: SnapshotDataKind,
+) -> Result<Vec<u8>, Error> {
+    let args = ReadCanisterSnapshotDataArgs {
+        canister_id: canister_pid,
+        snapshot_id,
+        kind,
+    };
+
+    let res: ReadCanisterSnapshotDataResult = Call::unbounded_wait(
+        Principal::management_canister(),
+        "read_canister_snapshot_data",
+    )
+    .with_arg(&args)
+    .await
+    .map_err(InterfaceError::from)?
+    .candid()
+    .map_err(InterfaceError::from)?;
+
+    Ok(res.chunk)
+}
+
+// upload_canister_snapshot_metadata
+// returns the id of the new (empty) snapshot
+pub async fn upload_canister_snapshot_metadata(
+    args: UploadCanisterSnapshotMetadataArgs,
+) -> Result<SnapshotId, Error> {
+    let res: UploadCanisterSnapshotMetadataResult = Call::unbounded_wait(
+        Principal::management_canister(),
+        "upload_canister_snapshot_metadata",
+    )
+    .with_arg(&args)
+    .await
+    .map_err(InterfaceError::from)?
+    .candid()
+    .map_err(InterfaceError::from)?;
+
+    Ok(res.snapshot_id)
+}
+
+// upload_canister_snapshot_data
+pub async fn upload_canister_snapshot_data(
+    canister_pid: Principal,
+    snapshot_id: SnapshotId,
+    kind: SnapshotDataOffset,
+    chunk: Vec<u8>,
+) -> Result<(), Error> {
+    let args = UploadCanisterSnapshotDataArgs {
+        canister_id: canister_pid,
+        snapshot_id,
+        kind,
+        chunk,
+    };
+
+    Call::unbounded_wait(
+        Principal::management_canister(),
+        "upload_canister_snapshot_data",
+    )
+    .with_arg(&args)
+    .await
+    .map_err(InterfaceError::from)?;
+
+    Ok(())
+}
@@ -18,6 +18,7 @@ pub mod export {
 }
 
 pub use Error as IcuError;
+pub use icu_macros::{query, update};
 
 ///
 /// Prelude
@@ -31,7 +32,7 @@ pub mod prelude {
             update,
         },
         guard::{guard_query, guard_update},
-        icu_register_memory, icu_start, icu_start_root, log, perf, perf_start,
+        icu_inspect_message, icu_register_memory, icu_start, icu_start_root, log, perf, perf_start,
         types::{CanisterType, Cycles},
     };
 }
@@ -65,6 +66,9 @@ pub const TEST: CanisterType = CanisterType::new("test");
 ///
 /// top level error should handle all sub-errors, but not expose the child candid types
 ///
+/// RateLimited : AuthError::RateLimited, kept apart so a throttled call can be told
+///               from a denial without reading the message
+///
 
 #[derive(CandidType, Debug, Deserialize, ThisError)]
 pub enum Error {
@@ -86,6 +90,9 @@ pub enum Error {
     #[error("{0}")]
     OpsError(String),
 
+    #[error("{0}")]
+    RateLimited(String),
+
     #[error("{0}")]
     StateError(String),
 }
@@ -100,7 +107,6 @@ macro_rules! from_to_string {
     };
 }
 
-from_to_string!(auth::AuthError, AuthError);
 from_to_string!(config::ConfigError, ConfigError);
 from_to_string!(env::EnvError, EnvError);
 from_to_string!(interface::InterfaceError, InterfaceError);
@@ -108,6 +114,15 @@ from_to_string!(memory::MemoryError, MemoryError);
 from_to_string!(ops::OpsError, OpsError);
 from_to_string!(state::StateError, StateError);
 
+impl From<auth::AuthError> for Error {
+    fn from(e: auth::AuthError) -> Self {
+        match e {
+            auth::AuthError::RateLimited(..) => Self::RateLimited(e.to_string()),
+            e => Self::AuthError(e.to_string()),
+        }
+    }
+}
+
 ///
 /// Log
 ///
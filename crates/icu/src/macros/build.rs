@@ -14,9 +14,30 @@ macro_rules! icu_build {
         let cfg_str = std::fs::read_to_string(&cfg_path)
             .unwrap_or_else(|e| panic!("Failed to read {}: {e}", cfg_path.display()));
 
+        // Pick the profile, ICU_PROFILE wins, otherwise the dfx network if the
+        // config has a [profile.<network>] for it
+        println!("cargo:rerun-if-env-changed=ICU_PROFILE");
+        println!("cargo:rerun-if-env-changed=DFX_NETWORK");
+        let profiles = icu::config::Config::profile_names(&cfg_str)
+            .expect("Invalid ICU config");
+        let profile = std::env::var("ICU_PROFILE").ok().or_else(|| {
+            std::env::var("DFX_NETWORK")
+                .ok()
+                .filter(|network| profiles.contains(network))
+        });
+
+        // Every profile has to be valid, not just the one being built
+        for name in &profiles {
+            icu::config::Config::check_profile(&cfg_str, name)
+                .unwrap_or_else(|e| panic!("Invalid ICU config profile {name}: {e}"));
+        }
+
         // Init config
-        icu::config::Config::init_from_toml(&cfg_str)
+        icu::config::Config::init_with_profile(&cfg_str, profile.as_deref())
             .expect("Invalid ICU config");
+        if let Some(profile) = &profile {
+            println!("cargo:rustc-env=ICU_CONFIG_PROFILE={profile}");
+        }
 
         // Export an ABSOLUTE path for include_str!
         let abs = cfg_path.canonicalize().expect("canonicalize icu config path");
@@ -24,14 +24,17 @@ macro_rules! icu_endpoints {
             Vec<Result<::icu::ops::response::UpgradeCanisterResponse, ::icu::Error>>,
             ::icu::Error,
         > {
-            $crate::auth_require_any!(::icu::auth::is_controller)?;
+            $crate::auth_endpoint!("icu_canister_upgrade_children", Controller)?;
 
             let mut results = Vec::new();
 
             for (child_pid, _) in $crate::memory::CanisterChildren::export() {
                 if canister_id.is_none() || canister_id == Some(child_pid) {
                     // Push the result (either Ok(resp) or Err(err)) into the vec
-                    let result = $crate::ops::request::upgrade_canister_request(child_pid).await;
+                    let result = $crate::audit!(
+                        "icu_canister_upgrade_children",
+                        $crate::ops::request::upgrade_canister_request(child_pid).await
+                    );
                     results.push(result);
                 }
             }
@@ -39,11 +42,62 @@ macro_rules! icu_endpoints {
             Ok(results)
         }
 
+        // icu_cycles_return
+        // root reclaims the cycles of a canister before it is deleted
+        #[::icu::cdk::update]
+        async fn icu_cycles_return() -> Result<::icu::types::Cycles, ::icu::Error> {
+            $crate::auth_internal!("icu_cycles_return", Root)?;
+
+            $crate::ops::pool::return_cycles().await
+        }
+
+        // icu_canister_children_update
+        // root keeps CanisterChildren in sync when a canister is reparented
+        // child_type : None means remove
+        #[::icu::cdk::update]
+        async fn icu_canister_children_update(
+            child_pid: ::candid::Principal,
+            child_type: Option<::icu::types::CanisterType>,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_internal!("icu_canister_children_update", Root)?;
+
+            match child_type {
+                Some(ty) => $crate::memory::CanisterChildren::insert(child_pid, ty),
+                None => $crate::memory::CanisterChildren::remove(&child_pid),
+            }
+
+            Ok(())
+        }
+
+        // icu_canister_children_reset
+        // root clears a clone's CanisterChildren, the snapshot brings the source's along
+        #[::icu::cdk::update]
+        async fn icu_canister_children_reset() -> Result<(), ::icu::Error> {
+            $crate::auth_internal!("icu_canister_children_reset", Root)?;
+
+            $crate::memory::CanisterChildren::clear();
+
+            Ok(())
+        }
+
+        // icu_canister_parents_update
+        // root pushes the new parent chain after a reparent
+        #[::icu::cdk::update]
+        async fn icu_canister_parents_update(
+            parents: Vec<::icu::memory::canister::CanisterEntry>,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_internal!("icu_canister_parents_update", Root)?;
+
+            $crate::memory::CanisterState::set_parents(parents);
+
+            Ok(())
+        }
+
         #[::icu::cdk::update]
         async fn icu_state_update(
             bundle: ::icu::ops::state::StateBundle,
         ) -> Result<(), ::icu::Error> {
-            $crate::auth_require_any!(::icu::auth::is_parent)?;
+            $crate::auth_internal!("icu_state_update", Parent)?;
 
             $crate::ops::state::save_state(&bundle);
 
@@ -54,12 +108,53 @@ macro_rules! icu_endpoints {
         async fn icu_state_cascade(
             bundle: ::icu::ops::state::StateBundle,
         ) -> Result<(), ::icu::Error> {
-            $crate::auth_require_any!(::icu::auth::is_parent)?;
+            $crate::auth_internal!("icu_state_cascade", Parent)?;
 
             $crate::ops::state::save_state(&bundle);
             $crate::ops::state::cascade(&bundle).await
         }
 
+        //
+        // ICU ROLE ENDPOINTS
+        //
+
+        // icu_role_grant
+        // cascade : also replace the roles of every canister below this one
+        #[::icu::cdk::update]
+        async fn icu_role_grant(
+            role: String,
+            pid: ::candid::Principal,
+            cascade: bool,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_endpoint!("icu_role_grant", Controller)?;
+
+            $crate::audit!(
+                "icu_role_grant",
+                $crate::ops::role::grant_role(&role, pid, cascade).await
+            )
+        }
+
+        #[::icu::cdk::update]
+        async fn icu_role_revoke(
+            role: String,
+            pid: ::candid::Principal,
+            cascade: bool,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_endpoint!("icu_role_revoke", Controller)?;
+
+            $crate::audit!(
+                "icu_role_revoke",
+                $crate::ops::role::revoke_role(&role, pid, cascade).await
+            )
+        }
+
+        #[::icu::cdk::query]
+        async fn icu_role_list() -> Result<::icu::memory::RoleRegistryView, ::icu::Error> {
+            $crate::auth_endpoint!("icu_role_list", Controller)?;
+
+            Ok($crate::memory::RoleRegistry::export())
+        }
+
         //
         // ICRC ENDPOINTS
         //
@@ -119,6 +214,53 @@ macro_rules! icu_endpoints {
             $crate::memory::CanisterDirectory::export()
         }
 
+        #[::icu::cdk::query]
+        async fn icu_audit_log(
+            offset: u64,
+            limit: u64,
+        ) -> Result<::icu::memory::AuditLogPage, ::icu::Error> {
+            $crate::auth_endpoint!("icu_audit_log", Controller)?;
+
+            Ok($crate::memory::AuditLog::page(offset, limit))
+        }
+
+        #[::icu::cdk::query]
+        async fn icu_audit_denials(
+            offset: u64,
+            limit: u64,
+        ) -> Result<::icu::memory::AuditLogPage, ::icu::Error> {
+            $crate::auth_endpoint!("icu_audit_denials", Controller)?;
+
+            Ok($crate::memory::AuditLog::denials(offset, limit))
+        }
+
+        #[::icu::cdk::query]
+        fn icu_whitelist_get() -> ::icu::memory::WhitelistData {
+            $crate::memory::Whitelist::export()
+        }
+
+        #[::icu::cdk::query]
+        fn icu_config_profile() -> Option<String> {
+            $crate::config::Config::profile()
+        }
+
+        #[::icu::cdk::query]
+        async fn icu_config() -> Result<::icu::config::ConfigView, ::icu::Error> {
+            $crate::auth_endpoint!("icu_config", Controller)?;
+
+            $crate::ops::config::config_view()
+        }
+
+        #[::icu::cdk::query]
+        fn icu_config_hash() -> Vec<u8> {
+            $crate::config::Config::hash()
+        }
+
+        #[::icu::cdk::query]
+        fn icu_feature_flags() -> ::icu::memory::FeatureFlagsData {
+            $crate::memory::FeatureFlags::export()
+        }
+
         #[::icu::cdk::query]
         fn icu_cycle_tracker() -> ::icu::memory::CycleTrackerView {
             $crate::memory::CycleTracker::export()
@@ -146,11 +288,19 @@ macro_rules! icu_endpoints {
         async fn icu_delegation_register(
             args: ::icu::state::delegation::RegisterSessionArgs,
         ) -> Result<(), ::icu::Error> {
-            $crate::auth_require_any!(::icu::auth::is_whitelisted)?;
+            $crate::auth_endpoint!("icu_delegation_register", Whitelisted)?;
+            $crate::auth_require_all!(::icu::auth::rate_limit("icu_delegation_register"))?;
 
             $crate::state::delegation::DelegationRegistry::register_session(msg_caller(), args)
         }
 
+        // icu_delegation_confirm
+        // called by the session key, a registered session only resolves once it's confirmed
+        #[::icu::cdk::update]
+        async fn icu_delegation_confirm(wallet_pid: Principal) -> Result<(), ::icu::Error> {
+            $crate::state::delegation::DelegationRegistry::confirm_session(msg_caller(), wallet_pid)
+        }
+
         #[::icu::cdk::update]
         async fn icu_delegation_revoke(pid: Principal) -> Result<(), ::icu::Error> {
             use ::icu::auth::{is_parent, is_principal};
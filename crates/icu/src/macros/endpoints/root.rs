@@ -7,12 +7,81 @@ macro_rules! icu_endpoints_root {
         // @todo eventually this will cascade down from an orchestrator canister
         #[::icu::cdk::update]
         async fn icu_app(cmd: ::icu::memory::app_state::AppCommand) -> Result<(), ::icu::Error> {
-            ::icu::memory::AppState::command(cmd)?;
+            $crate::auth_endpoint!("icu_app", Controller)?;
 
-            let bundle = ::icu::ops::state::StateBundle::app_state();
-            ::icu::ops::state::cascade(&bundle).await?;
+            $crate::audit!(
+                "icu_app",
+                async {
+                    ::icu::memory::AppState::command(cmd)?;
 
-            Ok(())
+                    let bundle = ::icu::ops::state::StateBundle::app_state();
+                    ::icu::ops::state::cascade(&bundle).await
+                }
+                .await
+            )
+        }
+
+        // icu_whitelist
+        // edit the whitelist on root and cascade it to every canister
+        #[::icu::cdk::update]
+        async fn icu_whitelist(
+            cmd: ::icu::memory::whitelist::WhitelistCommand,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_endpoint!("icu_whitelist", Controller)?;
+
+            $crate::audit!(
+                "icu_whitelist",
+                async {
+                    ::icu::memory::Whitelist::command(cmd)?;
+
+                    let bundle = ::icu::ops::state::StateBundle::whitelist();
+                    ::icu::ops::state::cascade(&bundle).await
+                }
+                .await
+            )
+        }
+
+        // icu_feature_flag
+        // edit the feature flags on root and cascade them to every canister
+        #[::icu::cdk::update]
+        async fn icu_feature_flag(
+            cmd: ::icu::memory::feature_flags::FeatureFlagCommand,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_endpoint!("icu_feature_flag", Controller)?;
+
+            $crate::audit!(
+                "icu_feature_flag",
+                async {
+                    ::icu::memory::FeatureFlags::command(cmd)?;
+
+                    let bundle = ::icu::ops::state::StateBundle::feature_flags();
+                    ::icu::ops::state::cascade(&bundle).await
+                }
+                .await
+            )
+        }
+
+        // icu_config_update
+        // replace or patch the config at runtime, root validates it and cascades it
+        #[::icu::cdk::update]
+        async fn icu_config_update(
+            cmd: ::icu::memory::config_overlay::ConfigCommand,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_endpoint!("icu_config_update", Controller)?;
+
+            $crate::audit!(
+                "icu_config_update",
+                ::icu::ops::config::update_config(cmd).await
+            )
+        }
+
+        // icu_config_hashes
+        // the config hash of every canister in the tree, stale ones are flagged
+        #[::icu::cdk::update]
+        async fn icu_config_hashes() -> Result<Vec<::icu::ops::config::ConfigHash>, ::icu::Error> {
+            $crate::auth_endpoint!("icu_config_hashes", Controller)?;
+
+            ::icu::ops::config::config_hashes().await
         }
 
         // icu_response
@@ -22,7 +91,7 @@ macro_rules! icu_endpoints_root {
         async fn icu_response(
             request: ::icu::ops::request::Request,
         ) -> Result<::icu::ops::response::Response, ::icu::Error> {
-            $crate::auth_require_any!(::icu::auth::is_root, ::icu::auth::is_app)?;
+            $crate::auth_internal!("icu_response", Root, App)?;
 
             let response = ::icu::ops::response::response(request).await?;
 
@@ -35,25 +104,100 @@ macro_rules! icu_endpoints_root {
         async fn icu_canister_status(
             pid: Principal,
         ) -> Result<::icu::cdk::mgmt::CanisterStatusResult, ::icu::Error> {
+            $crate::auth_endpoint!("icu_canister_status", Controller)?;
+
             ::icu::interface::ic::canister_status(pid).await
         }
 
+        // icu_adopt_canister
+        // bring a canister created outside of ICU into the registry or the pool
+        #[update]
+        async fn icu_adopt_canister(
+            pid: Principal,
+            target: ::icu::ops::canister::AdoptTarget,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_endpoint!("icu_adopt_canister", Controller)?;
+
+            $crate::audit!(
+                "icu_adopt_canister",
+                ::icu::ops::canister::adopt_canister(pid, target).await
+            )
+        }
+
+        // icu_reparent_canister
+        // move a canister (and its subtree) under a different parent
+        #[update]
+        async fn icu_reparent_canister(
+            pid: Principal,
+            new_parent_pid: Principal,
+        ) -> Result<(), ::icu::Error> {
+            $crate::auth_endpoint!("icu_reparent_canister", Controller)?;
+
+            $crate::audit!(
+                "icu_reparent_canister",
+                ::icu::ops::canister::reparent_canister(pid, new_parent_pid).await
+            )
+        }
+
+        // icu_clone_canister
+        // create a new canister with a copy of another one's state
+        #[update]
+        async fn icu_clone_canister(pid: Principal) -> Result<Principal, ::icu::Error> {
+            $crate::auth_endpoint!("icu_clone_canister", Controller)?;
+
+            $crate::audit!(
+                "icu_clone_canister",
+                ::icu::ops::clone::clone_canister(pid).await
+            )
+        }
+
+        // icu_canister_command
+        // drain, stop or start any canister in the registry
+        #[update]
+        async fn icu_canister_command(
+            pid: Principal,
+            cmd: ::icu::ops::lifecycle::CanisterCommand,
+        ) -> Result<::icu::memory::canister::registry::CanisterStatus, ::icu::Error> {
+            $crate::auth_endpoint!("icu_canister_command", Controller)?;
+
+            $crate::audit!(
+                "icu_canister_command",
+                ::icu::ops::lifecycle::canister_command(pid, cmd).await
+            )
+        }
+
         ///
         /// POOL ENDPOINTS
         ///
 
         #[update]
         async fn icu_create_pool_canister() -> Result<Principal, ::icu::Error> {
-            $crate::auth_require_any!(::icu::auth::is_controller)?;
+            $crate::auth_endpoint!("icu_create_pool_canister", Controller)?;
 
-            ::icu::ops::pool::create_pool_canister().await
+            $crate::audit!(
+                "icu_create_pool_canister",
+                ::icu::ops::pool::create_pool_canister().await
+            )
         }
 
         #[update]
         async fn icu_move_canister_to_pool(pid: Principal) -> Result<(), ::icu::Error> {
-            $crate::auth_require_any!(::icu::auth::is_controller)?;
+            $crate::auth_endpoint!("icu_move_canister_to_pool", Controller)?;
+
+            $crate::audit!(
+                "icu_move_canister_to_pool",
+                ::icu::ops::pool::move_canister_to_pool(pid).await
+            )
+        }
+
+        #[::icu::cdk::update]
+        async fn icu_release_pool_canister(pid: Principal) -> Result<(), ::icu::Error> {
+            $crate::auth_endpoint!("icu_release_pool_canister", Controller)?;
 
-            ::icu::ops::pool::move_canister_to_pool(pid).await
+            $crate::audit!(
+                "icu_release_pool_canister",
+                ::icu::ops::pool::release_pool_canister(pid)
+            )
         }
 
         ///
@@ -65,6 +209,16 @@ macro_rules! icu_endpoints_root {
             $crate::memory::CanisterPool::export()
         }
 
+        #[::icu::cdk::query]
+        fn icu_canister_pool_quarantined() -> ::icu::memory::CanisterPoolView {
+            $crate::memory::CanisterPool::quarantined()
+        }
+
+        #[::icu::cdk::query]
+        fn icu_canister_pool_report() -> ::icu::state::pool::PoolReportView {
+            $crate::state::pool::PoolReport::export()
+        }
+
         #[::icu::cdk::query]
         fn icu_canister_registry() -> ::icu::memory::CanisterRegistryView {
             $crate::memory::CanisterRegistry::export()
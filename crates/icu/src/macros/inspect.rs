@@ -0,0 +1,21 @@
+// icu_inspect_message
+// optional, generates canister_inspect_message with the [inspect] rules from icu.toml
+//
+// icu_inspect_message!();
+// icu_inspect_message!(|method: &str| -> Result<(), String> { ... });
+#[macro_export]
+macro_rules! icu_inspect_message {
+    () => {
+        $crate::icu_inspect_message!(|_: &str| -> Result<(), String> { Ok(()) });
+    };
+
+    ($rule:expr) => {
+        #[::icu::cdk::inspect_message]
+        fn inspect_message() {
+            match ::icu::guard::guard_inspect($rule) {
+                Ok(()) => ::icu::cdk::api::accept_message(),
+                Err(e) => ::icu::log!(::icu::Log::Warn, "🚫 inspect_message: {e}"),
+            }
+        }
+    };
+}
@@ -1,8 +1,20 @@
 pub mod build;
 pub mod endpoints;
+pub mod inspect;
 pub mod memory;
 pub mod start;
 
+// audit
+// records the result of an admin action in the AuditLog and passes it back
+#[macro_export]
+macro_rules! audit {
+    ($method:literal, $res:expr) => {{
+        let res = $res;
+        $crate::memory::AuditLog::record_result($method, &res);
+        res
+    }};
+}
+
 // log
 #[macro_export]
 macro_rules! log {
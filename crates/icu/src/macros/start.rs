@@ -5,7 +5,12 @@ macro_rules! __icu_load_config {
         #[cfg(icu_config)]
         {
             let config_str = include_str!(env!("ICU_CONFIG_PATH"));
-            $crate::config::Config::init_from_toml(config_str).unwrap()
+            $crate::config::Config::init_with_profile(
+                config_str,
+                option_env!("ICU_CONFIG_PROFILE"),
+            )
+            .unwrap();
+            $crate::ops::config::apply_stored_overlay();
         }
     };
 }
@@ -43,6 +48,7 @@ macro_rules! icu_start {
 
         fn __icu_shared_setup() {
             ::icu::__icu_load_config!();
+            ::icu::memory::AppState::tick_maintenance();
             ::icu::memory::CycleTracker::start();
             icu_setup();
         }
@@ -86,9 +92,12 @@ macro_rules! icu_start_root {
 
         fn __icu_shared_setup() {
             ::icu::__icu_load_config!();
+            ::icu::memory::AppState::tick_maintenance();
+            ::icu::memory::Whitelist::seed_from_config();
             ::icu::memory::CanisterPool::start();
             ::icu::memory::CycleTracker::start();
             ::icu::state::wasm::WasmRegistry::import(WASMS);
+            ::icu::ops::config::check_wasms();
             icu_setup();
         }
 
@@ -0,0 +1,83 @@
+use crate::{
+    cdk::timers::{TimerId, clear_timer, set_timer},
+    memory::{AppState, app_state::AppMode},
+    utils::time::{format_utc, now_secs},
+};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, time::Duration};
+
+//
+// MAINTENANCE_TIMER
+// only ever one, set for the next start or end
+//
+
+thread_local! {
+    static MAINTENANCE_TIMER: RefCell<Option<TimerId>> = const { RefCell::new(None) };
+}
+
+///
+/// Maintenance
+/// a scheduled change of the app mode
+///
+/// mode     : Readonly or Disabled, applied at start
+/// start    : unix seconds
+/// end      : optional unix seconds, the app goes back to the mode it had before
+/// reason   : shown to callers by the guards
+/// previous : set once the window has started
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Maintenance {
+    pub mode: AppMode,
+    pub start: u64,
+    pub end: Option<u64>,
+    pub reason: String,
+
+    #[serde(default)]
+    pub previous: Option<AppMode>,
+}
+
+impl Maintenance {
+    #[must_use]
+    pub const fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    // next_change
+    // when the timer next has to fire
+    #[must_use]
+    pub const fn next_change(&self) -> Option<u64> {
+        if self.is_active() {
+            self.end
+        } else {
+            Some(self.start)
+        }
+    }
+
+    // notice
+    // the reason plus when it's expected to be over, for guard errors
+    #[must_use]
+    pub fn notice(&self) -> String {
+        match self.end {
+            Some(end) => format!("{} (until {})", self.reason, format_utc(end)),
+            None => self.reason.clone(),
+        }
+    }
+}
+
+// set_maintenance_timer
+// replaces the current timer, at None just clears it
+pub(super) fn set_maintenance_timer(at: Option<u64>) {
+    MAINTENANCE_TIMER.with_borrow_mut(|slot| {
+        if let Some(id) = slot.take() {
+            clear_timer(id);
+        }
+
+        if let Some(at) = at {
+            let delay = Duration::from_secs(at.saturating_sub(now_secs()));
+
+            *slot = Some(set_timer(delay, AppState::tick_maintenance));
+        }
+    });
+}
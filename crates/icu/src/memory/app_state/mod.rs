@@ -1,3 +1,5 @@
+mod maintenance;
 mod state;
 
+pub use maintenance::*;
 pub use state::*;
@@ -1,13 +1,19 @@
 use crate::{
     Error, Log,
     cdk::structures::{DefaultMemoryImpl, Memory, cell::Cell, memory::VirtualMemory},
+    config::Config,
     icu_register_memory, impl_storable_unbounded, log,
-    memory::{APP_STATE_MEMORY_ID, MemoryError},
+    memory::{
+        APP_STATE_MEMORY_ID, MemoryError,
+        app_state::{Maintenance, maintenance::set_maintenance_timer},
+    },
+    types::CanisterType,
+    utils::time::now_secs,
 };
-use candid::CandidType;
+use candid::{CandidType, Principal};
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::{cell::RefCell, collections::BTreeMap};
 use thiserror::Error as ThisError;
 
 //
@@ -30,12 +36,32 @@ thread_local! {
 pub enum AppStateError {
     #[error("app is already in {0} mode")]
     AlreadyInMode(AppMode),
+
+    #[error("{0} is already in {1} mode")]
+    OverrideAlreadyInMode(String, AppMode),
+
+    #[error("{0} already inherits the app mode")]
+    OverrideAlreadyInherits(String),
+
+    #[error("capability {0} is not declared in [app] capabilities")]
+    UnknownCapability(String),
+
+    #[error("canister type {0} is not in the config")]
+    UnknownCanisterType(CanisterType),
+
+    #[error("invalid maintenance window: {0}")]
+    InvalidMaintenance(String),
+
+    #[error("a maintenance window is already scheduled, cancel it first")]
+    MaintenanceScheduled,
+
+    #[error("no maintenance window is scheduled")]
+    NoMaintenance,
 }
 
 ///
 /// AppMode
-/// used for the query/update guards
-/// Eventually we'll have more granularity overall
+/// used for the query/update guards, either for the whole app or a capability
 ///
 
 #[derive(
@@ -48,15 +74,67 @@ pub enum AppMode {
     Disabled,
 }
 
+impl AppMode {
+    // restrict
+    // whichever of the two modes allows less
+    #[must_use]
+    pub const fn restrict(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Disabled, _) | (_, Self::Disabled) => Self::Disabled,
+            (Self::Readonly, _) | (_, Self::Readonly) => Self::Readonly,
+            _ => Self::Enabled,
+        }
+    }
+}
+
 ///
 /// AppCommand
 ///
+/// Start / Readonly / Stop   : the mode of the whole app, ends an active maintenance window
+/// Capability(name, command) : the mode of one capability, ie. "trading"
+/// CanisterType(ty, command) : the mode of every canister of one type, ie. "game"
+/// Maintenance(window)       : schedule a mode change, see Maintenance
+/// CancelMaintenance         : drop the window, if it started the previous mode comes back
+///
 
-#[derive(CandidType, Clone, Copy, Debug, Deserialize, Display, Eq, PartialEq)]
+#[derive(CandidType, Clone, Debug, Deserialize, Display, Eq, PartialEq)]
 pub enum AppCommand {
     Start,
     Readonly,
     Stop,
+    #[display("Capability({_0}, {_1})")]
+    Capability(String, OverrideCommand),
+    #[display("CanisterType({_0}, {_1})")]
+    CanisterType(CanisterType, OverrideCommand),
+    #[display("Maintenance({})", _0.reason)]
+    Maintenance(Maintenance),
+    CancelMaintenance,
+}
+
+///
+/// OverrideCommand
+/// for a capability or canister type, Inherit removes the override so it
+/// follows the app mode again
+///
+
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, Display, Eq, PartialEq)]
+pub enum OverrideCommand {
+    Start,
+    Readonly,
+    Stop,
+    Inherit,
+}
+
+impl OverrideCommand {
+    #[must_use]
+    pub const fn mode(self) -> Option<AppMode> {
+        match self {
+            Self::Start => Some(AppMode::Enabled),
+            Self::Readonly => Some(AppMode::Readonly),
+            Self::Stop => Some(AppMode::Disabled),
+            Self::Inherit => None,
+        }
+    }
 }
 
 ///
@@ -71,16 +149,83 @@ impl AppState {
         APP_STATE.with_borrow(AppStateCore::get_mode)
     }
 
+    #[must_use]
+    pub fn get_capability_mode(name: &str) -> AppMode {
+        APP_STATE.with_borrow(|core| core.get_capability_mode(name))
+    }
+
+    #[must_use]
+    pub fn get_canister_type_mode(ty: &CanisterType) -> AppMode {
+        APP_STATE.with_borrow(|core| core.get_canister_type_mode(ty))
+    }
+
+    // get_canister_mode
+    // the mode for one canister, its type override and any pin applied
+    #[must_use]
+    pub fn get_canister_mode(pid: Principal, ty: Option<&CanisterType>) -> AppMode {
+        APP_STATE.with_borrow(|core| core.export().canister_mode(pid, ty))
+    }
+
+    // pin_canister
+    // root only, used by canister_command to hold a drained canister in a mode
+    pub fn pin_canister(pid: Principal, mode: AppMode) {
+        APP_STATE.with_borrow_mut(|core| core.pin_canister(pid, mode));
+    }
+
+    pub fn unpin_canister(pid: Principal) {
+        APP_STATE.with_borrow_mut(|core| core.unpin_canister(pid));
+    }
+
+    // get_maintenance
+    // only returns a window that has started
+    #[must_use]
+    pub fn get_maintenance() -> Option<Maintenance> {
+        APP_STATE.with_borrow(|core| core.get_maintenance().filter(Maintenance::is_active))
+    }
+
     pub fn set_mode(mode: AppMode) {
         APP_STATE.with_borrow_mut(|core| core.set_mode(mode));
     }
 
+    // command
+    // capabilities and canister types have to be in the config first
     pub fn command(cmd: AppCommand) -> Result<(), Error> {
-        APP_STATE.with_borrow_mut(|core| core.command(cmd))
+        match &cmd {
+            AppCommand::Capability(name, _)
+                if !Config::try_get()?.app.capabilities.contains(name) =>
+            {
+                Err(MemoryError::from(AppStateError::UnknownCapability(
+                    name.clone(),
+                )))?;
+            }
+            AppCommand::CanisterType(ty, _)
+                if *ty != CanisterType::ROOT && !Config::try_get()?.canisters.contains_key(ty) =>
+            {
+                Err(MemoryError::from(AppStateError::UnknownCanisterType(
+                    ty.clone(),
+                )))?;
+            }
+            _ => {}
+        }
+
+        APP_STATE.with_borrow_mut(|core| core.command(cmd))?;
+        Self::tick_maintenance();
+
+        Ok(())
+    }
+
+    // tick_maintenance
+    // starts or ends a maintenance window if it's due, then sets the timer for the next
+    // change.  Every canister runs its own, so the window holds even if root is busy
+    pub fn tick_maintenance() {
+        let next = APP_STATE.with_borrow_mut(|core| core.tick_maintenance(now_secs()));
+
+        set_maintenance_timer(next);
     }
 
     pub fn import(data: AppStateData) {
         APP_STATE.with_borrow_mut(|core| core.import(data));
+        Self::tick_maintenance();
     }
 
     #[must_use]
@@ -92,10 +237,105 @@ impl AppState {
 ///
 /// AppStateData
 ///
+/// mode         : the mode of the whole app
+/// capabilities   : per-capability overrides, a capability without one follows mode
+/// canister_types : per-type overrides, ie. only the game shards in Readonly
+/// maintenance    : a scheduled (or running) maintenance window
+/// pinned         : per-canister modes set by root when a canister is drained or stopped,
+///                  they travel with every cascade so a drained canister stays drained
+///
+/// overrides can only restrict, so Stop or a maintenance window still reaches everything
+///
 
-#[derive(CandidType, Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct AppStateData {
     mode: AppMode,
+
+    #[serde(default)]
+    capabilities: BTreeMap<String, AppMode>,
+
+    #[serde(default)]
+    canister_types: BTreeMap<CanisterType, AppMode>,
+
+    #[serde(default)]
+    maintenance: Option<Maintenance>,
+
+    #[serde(default)]
+    pinned: BTreeMap<Principal, AppMode>,
+}
+
+impl AppStateData {
+    #[must_use]
+    pub const fn new(mode: AppMode) -> Self {
+        Self {
+            mode,
+            capabilities: BTreeMap::new(),
+            canister_types: BTreeMap::new(),
+            maintenance: None,
+            pinned: BTreeMap::new(),
+        }
+    }
+
+    // with_mode
+    // changes the app mode, keeping the capability overrides
+    #[must_use]
+    pub fn with_mode(mut self, mode: AppMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    #[must_use]
+    pub const fn mode(&self) -> AppMode {
+        self.mode
+    }
+
+    #[must_use]
+    pub const fn capabilities(&self) -> &BTreeMap<String, AppMode> {
+        &self.capabilities
+    }
+
+    #[must_use]
+    pub const fn canister_types(&self) -> &BTreeMap<CanisterType, AppMode> {
+        &self.canister_types
+    }
+
+    // canister_type_mode
+    #[must_use]
+    pub fn canister_type_mode(&self, ty: &CanisterType) -> AppMode {
+        self.canister_types
+            .get(ty)
+            .map_or(self.mode, |mode| mode.restrict(self.mode))
+    }
+
+    // canister_mode
+    // the type override and the pin can only restrict the app mode
+    #[must_use]
+    pub fn canister_mode(&self, pid: Principal, ty: Option<&CanisterType>) -> AppMode {
+        let mode = ty.map_or(self.mode, |ty| self.canister_type_mode(ty));
+
+        self.pinned
+            .get(&pid)
+            .map_or(mode, |pinned| pinned.restrict(mode))
+    }
+
+    #[must_use]
+    pub const fn pinned(&self) -> &BTreeMap<Principal, AppMode> {
+        &self.pinned
+    }
+
+    #[must_use]
+    pub const fn maintenance(&self) -> Option<&Maintenance> {
+        self.maintenance.as_ref()
+    }
+
+    // capability_mode
+    // a capability can't be more open than the app itself
+    #[must_use]
+    pub fn capability_mode(&self, name: &str) -> AppMode {
+        self.capabilities
+            .get(name)
+            .map_or(self.mode, |mode| mode.restrict(self.mode))
+    }
 }
 
 impl_storable_unbounded!(AppStateData);
@@ -117,9 +357,20 @@ impl<M: Memory> AppStateCore<M> {
         self.cell.get().mode
     }
 
+    pub fn get_capability_mode(&self, name: &str) -> AppMode {
+        self.cell.get().capability_mode(name)
+    }
+
+    pub fn get_canister_type_mode(&self, ty: &CanisterType) -> AppMode {
+        self.cell.get().canister_type_mode(ty)
+    }
+
+    pub fn get_maintenance(&self) -> Option<Maintenance> {
+        self.cell.get().maintenance.clone()
+    }
+
     pub fn set_mode(&mut self, mode: AppMode) {
-        let mut cur = *self.cell.get(); // copy out the value
-        cur.mode = mode;
+        let cur = self.cell.get().clone().with_mode(mode);
         self.cell.set(cur);
     }
 
@@ -130,25 +381,184 @@ impl<M: Memory> AppStateCore<M> {
             AppCommand::Start => AppMode::Enabled,
             AppCommand::Readonly => AppMode::Readonly,
             AppCommand::Stop => AppMode::Disabled,
+            AppCommand::Capability(name, cmd) => {
+                let mut cur = self.cell.get().clone();
+                let label = format!("capability {name}");
+                set_override(&mut cur.capabilities, name, cmd, &label)?;
+                self.cell.set(cur);
+
+                return Ok(());
+            }
+            AppCommand::CanisterType(ty, cmd) => {
+                let mut cur = self.cell.get().clone();
+                let label = format!("canister type {ty}");
+                set_override(&mut cur.canister_types, ty, cmd, &label)?;
+                self.cell.set(cur);
+
+                return Ok(());
+            }
+            AppCommand::Maintenance(window) => {
+                return self.schedule_maintenance(window, now_secs());
+            }
+            AppCommand::CancelMaintenance => return self.cancel_maintenance(),
         };
 
         if old_mode == new_mode {
             return Err(MemoryError::from(AppStateError::AlreadyInMode(old_mode)))?;
         }
 
-        self.set_mode(new_mode);
+        // setting the mode by hand takes over from an active window
+        let mut cur = self.cell.get().clone().with_mode(new_mode);
+        if cur.maintenance.take_if(|m| m.is_active()).is_some() {
+            log!(Log::Warn, "app: maintenance window ended by a mode change");
+        }
+        self.cell.set(cur);
 
         log!(Log::Ok, "app: mode changed {old_mode} -> {new_mode}");
         Ok(())
     }
 
+    // schedule_maintenance
+    pub fn schedule_maintenance(&mut self, mut window: Maintenance, now: u64) -> Result<(), Error> {
+        let invalid = |msg: &str| MemoryError::from(AppStateError::InvalidMaintenance(msg.into()));
+
+        if window.mode == AppMode::Enabled {
+            Err(invalid("mode has to be Readonly or Disabled"))?;
+        }
+        if window.reason.trim().is_empty() {
+            Err(invalid("a reason is required"))?;
+        }
+        if let Some(end) = window.end
+            && (end <= window.start || end <= now)
+        {
+            Err(invalid("end has to be after start and in the future"))?;
+        }
+
+        let mut cur = self.cell.get().clone();
+        if cur.maintenance.is_some() {
+            Err(MemoryError::from(AppStateError::MaintenanceScheduled))?;
+        }
+
+        window.previous = None;
+        log!(
+            Log::Ok,
+            "app: maintenance scheduled at {} ({}): {}",
+            window.start,
+            window.mode,
+            window.notice()
+        );
+        cur.maintenance = Some(window);
+        self.cell.set(cur);
+
+        Ok(())
+    }
+
+    // cancel_maintenance
+    pub fn cancel_maintenance(&mut self) -> Result<(), Error> {
+        let mut cur = self.cell.get().clone();
+        let Some(window) = cur.maintenance.take() else {
+            return Err(MemoryError::from(AppStateError::NoMaintenance))?;
+        };
+
+        if let Some(previous) = window.previous {
+            cur.mode = previous;
+        }
+        self.cell.set(cur);
+
+        log!(Log::Ok, "app: maintenance cancelled ({})", window.reason);
+        Ok(())
+    }
+
+    // tick_maintenance
+    // applies whatever is due at now, returns when the next change is due
+    pub fn tick_maintenance(&mut self, now: u64) -> Option<u64> {
+        let mut cur = self.cell.get().clone();
+        let window = cur.maintenance.as_mut()?;
+        let mut changed = false;
+
+        if !window.is_active() && window.start <= now {
+            window.previous = Some(cur.mode);
+            cur.mode = window.mode;
+            changed = true;
+
+            log!(Log::Warn, "app: maintenance started: {}", window.notice());
+        }
+
+        if window.is_active() && window.end.is_some_and(|end| end <= now) {
+            cur.mode = window.previous.unwrap_or(cur.mode);
+            cur.maintenance = None;
+            changed = true;
+
+            log!(Log::Ok, "app: maintenance ended, back to {}", cur.mode);
+        }
+
+        let next = cur.maintenance.as_ref().and_then(Maintenance::next_change);
+        if changed {
+            self.cell.set(cur);
+        }
+
+        next
+    }
+
+    pub fn pin_canister(&mut self, pid: Principal, mode: AppMode) {
+        let mut cur = self.cell.get().clone();
+        cur.pinned.insert(pid, mode);
+        self.cell.set(cur);
+
+        log!(Log::Ok, "app: canister {pid} pinned to {mode}");
+    }
+
+    pub fn unpin_canister(&mut self, pid: Principal) {
+        let mut cur = self.cell.get().clone();
+        if cur.pinned.remove(&pid).is_some() {
+            self.cell.set(cur);
+
+            log!(Log::Ok, "app: canister {pid} unpinned");
+        }
+    }
+
     pub fn import(&mut self, data: AppStateData) {
         self.cell.set(data);
     }
 
     pub fn export(&self) -> AppStateData {
-        *self.cell.get()
+        self.cell.get().clone()
+    }
+}
+
+// set_override
+// label is only for errors and logs, ie. "capability trading"
+fn set_override<K: Ord>(
+    map: &mut BTreeMap<K, AppMode>,
+    key: K,
+    cmd: OverrideCommand,
+    label: &str,
+) -> Result<(), Error> {
+    let old_mode = map.get(&key).copied();
+    let new_mode = cmd.mode();
+
+    match (old_mode, new_mode) {
+        (Some(old), Some(new)) if old == new => {
+            Err(MemoryError::from(AppStateError::OverrideAlreadyInMode(
+                label.to_string(),
+                old,
+            )))?;
+        }
+        (None, None) => {
+            Err(MemoryError::from(AppStateError::OverrideAlreadyInherits(
+                label.to_string(),
+            )))?;
+        }
+        (_, Some(new)) => {
+            map.insert(key, new);
+        }
+        (_, None) => {
+            map.remove(&key);
+        }
     }
+
+    log!(Log::Ok, "app: {label} changed {old_mode:?} -> {new_mode:?}");
+    Ok(())
 }
 
 ///
@@ -215,13 +625,167 @@ mod tests {
         let mut core = core();
         let data = AppStateData {
             mode: AppMode::Readonly,
+            capabilities: [("trading".to_string(), AppMode::Disabled)].into(),
+            canister_types: [(CanisterType::new("game"), AppMode::Readonly)].into(),
+            maintenance: None,
+            pinned: [(Principal::from_slice(&[1]), AppMode::Readonly)].into(),
         };
 
-        core.import(data);
+        core.import(data.clone());
         assert_eq!(core.export().mode, AppMode::Readonly);
 
         // After export we can reuse
         let exported = core.export();
         assert_eq!(exported, data);
     }
+
+    #[test]
+    fn capabilities_follow_and_override_the_app() {
+        let mut core = core();
+        core.set_mode(AppMode::Enabled);
+
+        // no override, follows the app
+        assert_eq!(core.get_capability_mode("trading"), AppMode::Enabled);
+
+        let trading = |cmd| AppCommand::Capability("trading".to_string(), cmd);
+        assert!(core.command(trading(OverrideCommand::Stop)).is_ok());
+        assert_eq!(core.get_capability_mode("trading"), AppMode::Disabled);
+        assert_eq!(core.get_capability_mode("signups"), AppMode::Enabled);
+        assert!(core.command(trading(OverrideCommand::Stop)).is_err());
+
+        // the app mode still caps an enabled capability
+        assert!(core.command(trading(OverrideCommand::Start)).is_ok());
+        core.set_mode(AppMode::Readonly);
+        assert_eq!(core.get_capability_mode("trading"), AppMode::Readonly);
+
+        // changing the app mode keeps the overrides
+        assert_eq!(core.export().capabilities().len(), 1);
+
+        assert!(core.command(trading(OverrideCommand::Inherit)).is_ok());
+        assert!(core.command(trading(OverrideCommand::Inherit)).is_err());
+        assert!(core.export().capabilities().is_empty());
+    }
+
+    fn window(start: u64, end: Option<u64>) -> Maintenance {
+        Maintenance {
+            mode: AppMode::Disabled,
+            start,
+            end,
+            reason: "database migration".to_string(),
+            previous: None,
+        }
+    }
+
+    #[test]
+    fn maintenance_window_runs_and_ends() {
+        let mut core = core();
+        core.set_mode(AppMode::Readonly);
+
+        assert!(
+            core.schedule_maintenance(window(100, Some(200)), 50)
+                .is_ok()
+        );
+        assert!(core.schedule_maintenance(window(100, None), 50).is_err());
+
+        // not yet
+        assert_eq!(core.tick_maintenance(99), Some(100));
+        assert_eq!(core.get_mode(), AppMode::Readonly);
+
+        // started, the next change is the end
+        assert_eq!(core.tick_maintenance(100), Some(200));
+        assert_eq!(core.get_mode(), AppMode::Disabled);
+        assert!(core.get_maintenance().is_some_and(|m| m.is_active()));
+
+        // ended, back to the mode from before
+        assert_eq!(core.tick_maintenance(250), None);
+        assert_eq!(core.get_mode(), AppMode::Readonly);
+        assert!(core.get_maintenance().is_none());
+    }
+
+    #[test]
+    fn maintenance_window_cancel_and_validation() {
+        let mut core = core();
+        core.set_mode(AppMode::Enabled);
+
+        // invalid windows
+        let mut enabled = window(100, None);
+        enabled.mode = AppMode::Enabled;
+        assert!(core.schedule_maintenance(enabled, 50).is_err());
+        assert!(
+            core.schedule_maintenance(window(100, Some(100)), 50)
+                .is_err()
+        );
+        assert!(core.cancel_maintenance().is_err());
+
+        // no end, runs until cancelled
+        assert!(core.schedule_maintenance(window(100, None), 50).is_ok());
+        assert_eq!(core.tick_maintenance(500), None);
+        assert_eq!(core.get_mode(), AppMode::Disabled);
+
+        assert!(core.cancel_maintenance().is_ok());
+        assert_eq!(core.get_mode(), AppMode::Enabled);
+    }
+
+    #[test]
+    fn canister_type_overrides() {
+        let mut core = core();
+        core.set_mode(AppMode::Enabled);
+
+        let game = CanisterType::new("game");
+        let lobby = CanisterType::new("lobby");
+
+        core.command(AppCommand::CanisterType(
+            game.clone(),
+            OverrideCommand::Readonly,
+        ))
+        .unwrap();
+        assert_eq!(core.get_canister_type_mode(&game), AppMode::Readonly);
+        assert_eq!(core.get_canister_type_mode(&lobby), AppMode::Enabled);
+
+        // stopping the app still stops everything
+        core.set_mode(AppMode::Disabled);
+        assert_eq!(core.get_canister_type_mode(&game), AppMode::Disabled);
+
+        core.command(AppCommand::CanisterType(game, OverrideCommand::Inherit))
+            .unwrap();
+        assert!(core.export().canister_types().is_empty());
+    }
+
+    #[test]
+    fn pinned_canisters() {
+        let mut core = core();
+        core.set_mode(AppMode::Enabled);
+
+        let drained = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+        let game = CanisterType::new("game");
+
+        core.pin_canister(drained, AppMode::Readonly);
+        assert_eq!(
+            core.export().canister_mode(drained, Some(&game)),
+            AppMode::Readonly
+        );
+        assert_eq!(
+            core.export().canister_mode(other, Some(&game)),
+            AppMode::Enabled
+        );
+
+        // an app mode change keeps the pin, and can still restrict further
+        core.command(AppCommand::Stop).unwrap();
+        core.command(AppCommand::Start).unwrap();
+        assert_eq!(
+            core.export().canister_mode(drained, None),
+            AppMode::Readonly
+        );
+        core.set_mode(AppMode::Disabled);
+        assert_eq!(
+            core.export().canister_mode(drained, None),
+            AppMode::Disabled
+        );
+
+        core.unpin_canister(drained);
+        core.set_mode(AppMode::Enabled);
+        assert_eq!(core.export().canister_mode(drained, None), AppMode::Enabled);
+        assert!(core.export().pinned().is_empty());
+    }
 }
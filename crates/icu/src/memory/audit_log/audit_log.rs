@@ -0,0 +1,307 @@
+use crate::{
+    Error,
+    cdk::{
+        api::msg_caller,
+        structures::{BTreeMap, DefaultMemoryImpl, Memory, memory::VirtualMemory},
+    },
+    icu_register_memory, impl_storable_unbounded,
+    memory::AUDIT_LOG_MEMORY_ID,
+    utils::time::now_secs,
+};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::VecDeque};
+
+///
+/// Constants
+///
+
+const MAX_ENTRIES: u64 = 10_000;
+const MAX_DENIALS: usize = 1_000;
+const MAX_PAGE_SIZE: u64 = 500;
+
+//
+// AUDIT_LOG
+// sequence number -> entry, oldest entries are dropped first
+//
+
+thread_local! {
+    static AUDIT_LOG: RefCell<AuditLogCore<VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(AuditLogCore::new(BTreeMap::init(icu_register_memory!(
+            AUDIT_LOG_MEMORY_ID
+        ))));
+
+    // anyone can cause a denial, so they go in a small heap ring instead of
+    // stable memory, and can't push admin actions out of the audit log
+    static DENIAL_LOG: RefCell<DenialLog> = RefCell::new(DenialLog::default());
+}
+
+///
+/// AuditOutcome
+///
+/// Denied    : an auth rule rejected the caller (only in the denial ring)
+/// Succeeded : an admin action went through
+/// Failed    : an admin action was allowed but returned an error
+///
+
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum AuditOutcome {
+    Denied,
+    Succeeded,
+    Failed,
+}
+
+///
+/// AuditEntry
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub caller: Principal,
+    pub method: Option<String>,
+    pub outcome: AuditOutcome,
+    pub error: Option<String>,
+}
+
+impl_storable_unbounded!(AuditEntry);
+
+///
+/// AuditLogPage
+/// newest first
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct AuditLogPage {
+    pub entries: Vec<(u64, AuditEntry)>,
+    pub total: u64,
+}
+
+///
+/// AuditLog
+///
+
+pub struct AuditLog;
+
+impl AuditLog {
+    fn record(
+        caller: Principal,
+        method: Option<&str>,
+        outcome: AuditOutcome,
+        error: Option<String>,
+    ) {
+        let entry = AuditEntry {
+            timestamp: now_secs(),
+            caller,
+            method: method.map(ToString::to_string),
+            outcome,
+            error,
+        };
+
+        AUDIT_LOG.with_borrow_mut(|core| core.record(entry));
+    }
+
+    // record_result
+    // for admin actions, records the outcome of the endpoint
+    pub fn record_result<T>(method: &str, res: &Result<T, Error>) {
+        match res {
+            Ok(_) => Self::record(msg_caller(), Some(method), AuditOutcome::Succeeded, None),
+            Err(e) => Self::record(
+                msg_caller(),
+                Some(method),
+                AuditOutcome::Failed,
+                Some(e.to_string()),
+            ),
+        }
+    }
+
+    // record_denied
+    // goes in the denial ring, not the audit log
+    pub fn record_denied(caller: Principal, method: Option<&str>, error: String) {
+        let entry = AuditEntry {
+            timestamp: now_secs(),
+            caller,
+            method: method.map(ToString::to_string),
+            outcome: AuditOutcome::Denied,
+            error: Some(error),
+        };
+
+        DENIAL_LOG.with_borrow_mut(|log| log.record(entry));
+    }
+
+    // denials
+    // the most recent auth denials, lost on upgrade
+    #[must_use]
+    pub fn denials(offset: u64, limit: u64) -> AuditLogPage {
+        DENIAL_LOG.with_borrow(|log| log.page(offset, limit))
+    }
+
+    #[must_use]
+    pub fn len() -> u64 {
+        AUDIT_LOG.with_borrow(AuditLogCore::len)
+    }
+
+    #[must_use]
+    pub fn is_empty() -> bool {
+        AUDIT_LOG.with_borrow(AuditLogCore::is_empty)
+    }
+
+    #[must_use]
+    pub fn page(offset: u64, limit: u64) -> AuditLogPage {
+        AUDIT_LOG.with_borrow(|core| core.page(offset, limit))
+    }
+}
+
+///
+/// AuditLogCore
+///
+
+pub struct AuditLogCore<M: Memory> {
+    map: BTreeMap<u64, AuditEntry, M>,
+}
+
+impl<M: Memory> AuditLogCore<M> {
+    pub const fn new(map: BTreeMap<u64, AuditEntry, M>) -> Self {
+        Self { map }
+    }
+
+    pub fn record(&mut self, entry: AuditEntry) {
+        let seq = self.map.last_key_value().map_or(0, |(k, _)| k + 1);
+        self.map.insert(seq, entry);
+
+        while self.map.len() > MAX_ENTRIES {
+            self.map.pop_first();
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn page(&self, offset: u64, limit: u64) -> AuditLogPage {
+        let limit = limit.min(MAX_PAGE_SIZE);
+
+        let entries = self
+            .map
+            .iter()
+            .rev()
+            .skip(usize::try_from(offset).unwrap_or(usize::MAX))
+            .take(usize::try_from(limit).unwrap_or(0))
+            .map(|e| (*e.key(), e.value()))
+            .collect();
+
+        AuditLogPage {
+            entries,
+            total: self.map.len(),
+        }
+    }
+}
+
+///
+/// DenialLog
+/// a heap ring of the last MAX_DENIALS denials
+///
+
+#[derive(Default)]
+pub struct DenialLog {
+    entries: VecDeque<(u64, AuditEntry)>,
+    next: u64,
+}
+
+impl DenialLog {
+    pub fn record(&mut self, entry: AuditEntry) {
+        self.entries.push_back((self.next, entry));
+        self.next += 1;
+
+        if self.entries.len() > MAX_DENIALS {
+            self.entries.pop_front();
+        }
+    }
+
+    #[must_use]
+    pub fn page(&self, offset: u64, limit: u64) -> AuditLogPage {
+        let limit = limit.min(MAX_PAGE_SIZE);
+
+        let entries = self
+            .entries
+            .iter()
+            .rev()
+            .skip(usize::try_from(offset).unwrap_or(usize::MAX))
+            .take(usize::try_from(limit).unwrap_or(0))
+            .cloned()
+            .collect();
+
+        AuditLogPage {
+            entries,
+            total: self.entries.len() as u64,
+        }
+    }
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn core() -> AuditLogCore<DefaultMemoryImpl> {
+        AuditLogCore::new(BTreeMap::init(DefaultMemoryImpl::default()))
+    }
+
+    fn entry(n: u64) -> AuditEntry {
+        AuditEntry {
+            timestamp: n,
+            caller: Principal::anonymous(),
+            method: Some("icu_app".to_string()),
+            outcome: AuditOutcome::Denied,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn pages_are_newest_first() {
+        let mut core = core();
+        for n in 0..5 {
+            core.record(entry(n));
+        }
+
+        let page = core.page(0, 2);
+        assert_eq!(page.total, 5);
+        let seqs: Vec<_> = page.entries.iter().map(|(k, _)| *k).collect();
+        assert_eq!(seqs, vec![4, 3]);
+
+        let page = core.page(4, 10);
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].1.timestamp, 0);
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let mut core = core();
+        for n in 0..=MAX_ENTRIES {
+            core.record(entry(n));
+        }
+
+        assert_eq!(core.len(), MAX_ENTRIES);
+        assert_eq!(core.map.first_key_value().unwrap().0, 1);
+    }
+
+    #[test]
+    fn denial_ring_is_bounded() {
+        let mut log = DenialLog::default();
+        for n in 0..MAX_DENIALS as u64 + 10 {
+            log.record(entry(n));
+        }
+
+        let page = log.page(0, 1);
+        assert_eq!(page.total, MAX_DENIALS as u64);
+        assert_eq!(page.entries[0].0, MAX_DENIALS as u64 + 9);
+        assert_eq!(log.entries.front().unwrap().0, 10);
+    }
+}
@@ -8,13 +8,13 @@ use crate::{
     config::Config,
     icu_register_memory, impl_storable_candid_unbounded, log,
     memory::CANISTER_POOL_MEMORY_ID,
-    ops::pool::create_pool_canister,
+    ops::pool::{create_pool_canister, reconcile_pool},
     types::Cycles,
     utils::time::now_secs,
 };
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use thiserror::Error as ThisError;
 
 //
@@ -28,6 +28,9 @@ thread_local! {
         )));
 
     static TIMER: RefCell<Option<TimerId>> = const { RefCell::new(None) };
+
+    // set while a reconcile is in flight, so timer ticks don't stack them up
+    static RECONCILING: Cell<bool> = const { Cell::new(false) };
 }
 
 const POOL_CHECK_TIMER: u64 = 30 * 60; // 30 mins
@@ -42,11 +45,21 @@ pub enum CanisterPoolError {}
 ///
 /// CanisterPoolEntry
 ///
+/// quarantined : the reason the canister failed verification, it won't be reused
+///
 
 #[derive(CandidType, Clone, Debug, Deserialize, Serialize)]
 pub struct CanisterPoolEntry {
     pub created_at: u64,
     pub cycles: Cycles,
+    pub quarantined: Option<String>,
+}
+
+impl CanisterPoolEntry {
+    #[must_use]
+    pub const fn is_ready(&self) -> bool {
+        self.quarantined.is_none()
+    }
 }
 
 impl_storable_candid_unbounded!(CanisterPoolEntry);
@@ -72,11 +85,13 @@ impl CanisterPool {
             let id = set_timer(crate::CANISTER_INIT_DELAY, || {
                 // do first track
                 let _ = Self::check();
+                Self::reconcile();
 
                 // now start the recurring interval
                 let interval_id =
                     set_timer_interval(std::time::Duration::from_secs(POOL_CHECK_TIMER), || {
                         let _ = Self::check();
+                        Self::reconcile();
                     });
 
                 TIMER.with_borrow_mut(|slot| *slot = Some(interval_id));
@@ -97,7 +112,7 @@ impl CanisterPool {
 
     #[must_use]
     pub fn check() -> bool {
-        let pool_size = CANISTER_POOL.with_borrow(CanisterPoolCore::len);
+        let pool_size = CANISTER_POOL.with_borrow(CanisterPoolCore::ready_len);
 
         if let Ok(canister) = Config::try_get() {
             let min_size = u64::from(canister.pool.minimum_size);
@@ -128,15 +143,84 @@ impl CanisterPool {
         false
     }
 
+    // reconcile
+    // refreshes cycles and tops up the pool in the background, skipped if the
+    // previous reconcile is still awaiting calls
+    pub fn reconcile() {
+        if RECONCILING.replace(true) {
+            log!(Log::Info, "💧 canister pool reconcile already running");
+            return;
+        }
+
+        spawn(async {
+            let _guard = ReconcileGuard;
+
+            if let Err(e) = reconcile_pool().await {
+                log!(Log::Error, "💧 canister pool reconcile failed: {e}");
+            }
+        });
+    }
+
+    #[must_use]
+    pub fn len() -> u64 {
+        CANISTER_POOL.with_borrow(CanisterPoolCore::len)
+    }
+
+    // ready_len
+    // number of canisters that can be allocated (ie. not quarantined)
+    #[must_use]
+    pub fn ready_len() -> u64 {
+        CANISTER_POOL.with_borrow(CanisterPoolCore::ready_len)
+    }
+
+    #[must_use]
+    pub fn get(pid: &Principal) -> Option<CanisterPoolEntry> {
+        CANISTER_POOL.with_borrow(|core| core.get(pid))
+    }
+
     pub fn register(pid: Principal, cycles: Cycles) {
         let entry = CanisterPoolEntry {
             created_at: now_secs(),
             cycles,
+            quarantined: None,
         };
 
         CANISTER_POOL.with_borrow_mut(|core| core.insert(pid, entry));
     }
 
+    // quarantine
+    // puts a canister back into the pool, flagged so it's never handed out again
+    pub fn quarantine(pid: Principal, mut entry: CanisterPoolEntry, reason: String) {
+        entry.quarantined = Some(reason);
+
+        CANISTER_POOL.with_borrow_mut(|core| core.insert(pid, entry));
+    }
+
+    // restore
+    // puts an entry back as it was, ie. after a failed allocation
+    pub fn restore(pid: Principal, entry: CanisterPoolEntry) {
+        CANISTER_POOL.with_borrow_mut(|core| core.insert(pid, entry));
+    }
+
+    // release
+    // removes a canister only if it's quarantined
+    #[must_use]
+    pub fn release(pid: &Principal) -> Option<CanisterPoolEntry> {
+        CANISTER_POOL.with_borrow_mut(|core| core.release(pid))
+    }
+
+    #[must_use]
+    pub fn quarantined() -> CanisterPoolView {
+        CANISTER_POOL.with_borrow(CanisterPoolCore::quarantined)
+    }
+
+    // update_cycles
+    // returns false if the canister is no longer in the pool
+    #[must_use]
+    pub fn update_cycles(pid: &Principal, cycles: Cycles) -> bool {
+        CANISTER_POOL.with_borrow_mut(|core| core.update_cycles(pid, cycles))
+    }
+
     #[must_use]
     pub fn pop_first() -> Option<(Principal, CanisterPoolEntry)> {
         CANISTER_POOL.with_borrow_mut(CanisterPoolCore::pop_first)
@@ -153,6 +237,19 @@ impl CanisterPool {
     }
 }
 
+///
+/// ReconcileGuard
+/// clears RECONCILING when the reconcile future completes or is dropped
+///
+
+struct ReconcileGuard;
+
+impl Drop for ReconcileGuard {
+    fn drop(&mut self) {
+        RECONCILING.set(false);
+    }
+}
+
 ///
 /// CanisterPoolCore
 ///
@@ -174,17 +271,41 @@ impl<M: Memory> CanisterPoolCore<M> {
         self.map.len()
     }
 
+    pub fn ready_len(&self) -> u64 {
+        self.map
+            .values()
+            .filter(CanisterPoolEntry::is_ready)
+            .count() as u64
+    }
+
+    pub fn get(&self, pid: &Principal) -> Option<CanisterPoolEntry> {
+        self.map.get(pid)
+    }
+
     pub fn insert(&mut self, pid: Principal, entry: CanisterPoolEntry) {
         self.map.insert(pid, entry);
     }
 
-    // gets the oldest canister in the pool
+    pub fn update_cycles(&mut self, pid: &Principal, cycles: Cycles) -> bool {
+        match self.map.get(pid) {
+            Some(mut entry) => {
+                entry.cycles = cycles;
+                self.map.insert(*pid, entry);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    // gets the oldest canister in the pool, skipping quarantined ones
     pub fn pop_first(&mut self) -> Option<(Principal, CanisterPoolEntry)> {
         let min_pid = self
             .map
-            .iter()
-            .min_by_key(|entry| entry.value().created_at)
-            .map(|entry| *entry.key())?;
+            .view()
+            .filter(|(_, entry)| entry.is_ready())
+            .min_by_key(|(_, entry)| entry.created_at)
+            .map(|(pid, _)| pid)?;
 
         self.map.remove(&min_pid).map(|entry| (min_pid, entry))
     }
@@ -193,7 +314,112 @@ impl<M: Memory> CanisterPoolCore<M> {
         self.map.remove(pid)
     }
 
+    pub fn release(&mut self, pid: &Principal) -> Option<CanisterPoolEntry> {
+        if self.map.get(pid)?.is_ready() {
+            return None;
+        }
+
+        self.map.remove(pid)
+    }
+
+    pub fn quarantined(&self) -> CanisterPoolView {
+        self.map
+            .view()
+            .filter(|(_, entry)| !entry.is_ready())
+            .collect()
+    }
+
     pub fn export(&self) -> CanisterPoolView {
         self.map.to_vec()
     }
 }
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdk::structures::DefaultMemoryImpl;
+
+    fn make_core() -> CanisterPoolCore<DefaultMemoryImpl> {
+        let map = BTreeMap::init(DefaultMemoryImpl::default());
+        CanisterPoolCore::new(map)
+    }
+
+    fn entry(created_at: u64, quarantined: Option<&str>) -> CanisterPoolEntry {
+        CanisterPoolEntry {
+            created_at,
+            cycles: Cycles::new(100),
+            quarantined: quarantined.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn pop_first_returns_oldest() {
+        let mut core = make_core();
+        core.insert(Principal::from_slice(&[1]), entry(20, None));
+        core.insert(Principal::from_slice(&[2]), entry(10, None));
+
+        let (pid, _) = core.pop_first().unwrap();
+        assert_eq!(pid, Principal::from_slice(&[2]));
+        assert_eq!(core.len(), 1);
+    }
+
+    #[test]
+    fn pop_first_skips_quarantined() {
+        let mut core = make_core();
+        core.insert(
+            Principal::from_slice(&[1]),
+            entry(10, Some("module installed")),
+        );
+        core.insert(Principal::from_slice(&[2]), entry(20, None));
+
+        assert_eq!(core.ready_len(), 1);
+
+        let (pid, _) = core.pop_first().unwrap();
+        assert_eq!(pid, Principal::from_slice(&[2]));
+
+        // only the quarantined one is left, and it's never handed out
+        assert!(core.pop_first().is_none());
+        assert_eq!(core.len(), 1);
+    }
+
+    #[test]
+    fn only_quarantined_canisters_are_released() {
+        let mut core = make_core();
+        let ready = Principal::from_slice(&[1]);
+        let suspect = Principal::from_slice(&[2]);
+        core.insert(ready, entry(10, None));
+        core.insert(suspect, entry(20, Some("controllers don't match")));
+
+        let quarantined = core.quarantined();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].0, suspect);
+
+        assert!(core.release(&ready).is_none());
+        assert!(core.release(&Principal::from_slice(&[3])).is_none());
+        assert_eq!(core.len(), 2);
+
+        let released = core.release(&suspect).unwrap();
+        assert_eq!(
+            released.quarantined.as_deref(),
+            Some("controllers don't match")
+        );
+        assert!(core.quarantined().is_empty());
+        assert_eq!(core.len(), 1);
+    }
+
+    #[test]
+    fn update_cycles_only_touches_existing() {
+        let mut core = make_core();
+        let pid = Principal::from_slice(&[1]);
+
+        assert!(!core.update_cycles(&pid, Cycles::new(5)));
+
+        core.insert(pid, entry(10, None));
+        assert!(core.update_cycles(&pid, Cycles::new(5)));
+        assert_eq!(core.get(&pid).unwrap().cycles, Cycles::new(5));
+    }
+}
@@ -2,7 +2,9 @@ use crate::{
     Error,
     cdk::structures::{BTreeMap, DefaultMemoryImpl, Memory, memory::VirtualMemory},
     icu_register_memory, impl_storable_unbounded,
-    memory::{CANISTER_REGISTRY_MEMORY_ID, MemoryError},
+    memory::{
+        CANISTER_REGISTRY_MEMORY_ID, MemoryError, app_state::AppMode, canister::CanisterEntry,
+    },
     types::CanisterType,
     utils::time::now_secs,
 };
@@ -26,11 +28,16 @@ thread_local! {
 ///
 /// CanisterStatus
 ///
+/// Draining : pinned to readonly ahead of maintenance, holds the mode it had before
+/// Stopped  : drained and stopped, holds the mode it had before
+///
 
 #[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum CanisterStatus {
     Created,
     Installed,
+    Draining(AppMode),
+    Stopped(AppMode),
 }
 
 ///
@@ -44,6 +51,9 @@ pub enum CanisterRegistryError {
 
     #[error("canister principal not found: {0}")]
     NotFound(Principal),
+
+    #[error("parent chain for {0} contains a loop")]
+    ParentLoop(Principal),
 }
 
 ///
@@ -57,6 +67,9 @@ pub struct CanisterRegistryEntry {
     pub status: CanisterStatus,
     pub module_hash: Option<Vec<u8>>,
     pub created_at: u64,
+
+    #[serde(default)]
+    pub cloned_from: Option<Principal>,
 }
 
 impl_storable_unbounded!(CanisterRegistryEntry);
@@ -78,6 +91,7 @@ impl CanisterRegistry {
             status: CanisterStatus::Installed,
             module_hash: None,
             created_at: now_secs(),
+            cloned_from: None,
         };
 
         CANISTER_REGISTRY.with_borrow_mut(|core| core.insert(root_pid, entry));
@@ -99,6 +113,22 @@ impl CanisterRegistry {
             status: CanisterStatus::Created,
             module_hash: None,
             created_at: now_secs(),
+            cloned_from: None,
+        };
+
+        CANISTER_REGISTRY.with_borrow_mut(|core| core.insert(pid, entry));
+    }
+
+    // adopt
+    // registers a canister that was installed outside of ICU
+    pub fn adopt(pid: Principal, ty: &CanisterType, parent: Principal, module_hash: Vec<u8>) {
+        let entry = CanisterRegistryEntry {
+            canister_type: ty.clone(),
+            parent_pid: Some(parent),
+            status: CanisterStatus::Installed,
+            module_hash: Some(module_hash),
+            created_at: now_secs(),
+            cloned_from: None,
         };
 
         CANISTER_REGISTRY.with_borrow_mut(|core| core.insert(pid, entry));
@@ -124,6 +154,47 @@ impl CanisterRegistry {
         })
     }
 
+    pub fn set_status(pid: Principal, status: CanisterStatus) -> Result<(), Error> {
+        CANISTER_REGISTRY.with_borrow_mut(|core| core.set_status(pid, status))
+    }
+
+    pub fn set_parent(pid: Principal, parent: Principal) -> Result<(), Error> {
+        CANISTER_REGISTRY.with_borrow_mut(|core| core.set_parent(pid, parent))
+    }
+
+    // mark_clone
+    // recorded as soon as the clone exists, so a half-built one can be traced back
+    pub fn mark_clone(pid: Principal, source: Principal) -> Result<(), Error> {
+        CANISTER_REGISTRY.with_borrow_mut(|core| core.mark_clone(pid, source))
+    }
+
+    // set_module_hash
+    // a loaded clone runs the source's module, not whatever wasm it was installed with
+    pub fn set_module_hash(pid: Principal, module_hash: Option<Vec<u8>>) -> Result<(), Error> {
+        CANISTER_REGISTRY.with_borrow_mut(|core| core.set_module_hash(pid, module_hash))
+    }
+
+    // parents
+    // the parent chain of a canister as CanisterState expects it, root first
+    pub fn parents(pid: Principal) -> Result<Vec<CanisterEntry>, Error> {
+        CANISTER_REGISTRY.with_borrow(|core| core.parents(pid))
+    }
+
+    // reparented_parents
+    // the parent chains pid and its subtree would have under new_parent, without
+    // touching the registry
+    pub fn reparented_parents(
+        pid: Principal,
+        new_parent: Principal,
+    ) -> Result<Vec<(Principal, Vec<CanisterEntry>)>, Error> {
+        CANISTER_REGISTRY.with_borrow(|core| core.reparented_parents(pid, new_parent))
+    }
+
+    #[must_use]
+    pub fn descendants(pid: Principal) -> Vec<Principal> {
+        CANISTER_REGISTRY.with_borrow(|core| core.descendants(pid))
+    }
+
     #[must_use]
     pub fn remove(pid: &Principal) -> Option<CanisterRegistryEntry> {
         CANISTER_REGISTRY.with_borrow_mut(|core| core.remove(pid))
@@ -180,7 +251,252 @@ impl<M: Memory> CanisterRegistryCore<M> {
         }
     }
 
+    pub fn set_parent(&mut self, pid: Principal, parent: Principal) -> Result<(), Error> {
+        let mut entry = self.try_get(pid)?;
+        entry.parent_pid = Some(parent);
+        self.map.insert(pid, entry);
+
+        Ok(())
+    }
+
+    pub fn mark_clone(&mut self, pid: Principal, source: Principal) -> Result<(), Error> {
+        let mut entry = self.try_get(pid)?;
+        entry.cloned_from = Some(source);
+        self.map.insert(pid, entry);
+
+        Ok(())
+    }
+
+    pub fn set_module_hash(
+        &mut self,
+        pid: Principal,
+        module_hash: Option<Vec<u8>>,
+    ) -> Result<(), Error> {
+        let mut entry = self.try_get(pid)?;
+        entry.module_hash = module_hash;
+        self.map.insert(pid, entry);
+
+        Ok(())
+    }
+
+    pub fn parents(&self, pid: Principal) -> Result<Vec<CanisterEntry>, Error> {
+        let mut parents = Vec::new();
+        let mut current = self.try_get(pid)?.parent_pid;
+
+        while let Some(parent_pid) = current {
+            // can't be deeper than the registry itself
+            if parents.len() as u64 >= self.map.len() {
+                Err(MemoryError::from(CanisterRegistryError::ParentLoop(pid)))?;
+            }
+
+            let entry = self.try_get(parent_pid)?;
+            parents.push(CanisterEntry {
+                canister_type: entry.canister_type,
+                principal: parent_pid,
+            });
+            current = entry.parent_pid;
+        }
+        parents.reverse();
+
+        Ok(parents)
+    }
+
+    pub fn reparented_parents(
+        &self,
+        pid: Principal,
+        new_parent: Principal,
+    ) -> Result<Vec<(Principal, Vec<CanisterEntry>)>, Error> {
+        let mut prefix = self.parents(new_parent)?;
+        prefix.push(CanisterEntry {
+            canister_type: self.try_get(new_parent)?.canister_type,
+            principal: new_parent,
+        });
+
+        let mut chains = vec![(pid, prefix.clone())];
+        for child in self.descendants(pid) {
+            // keep everything from pid down, swap whatever was above it
+            let parents = self.parents(child)?;
+            let below = parents
+                .iter()
+                .position(|p| p.principal == pid)
+                .map_or(&parents[..], |i| &parents[i..]);
+
+            let mut chain = prefix.clone();
+            chain.extend_from_slice(below);
+            chains.push((child, chain));
+        }
+
+        Ok(chains)
+    }
+
+    // descendants
+    // every canister below pid, parents before their children
+    pub fn descendants(&self, pid: Principal) -> Vec<Principal> {
+        let mut found = vec![pid];
+        let mut i = 0;
+
+        while i < found.len() {
+            let parent = found[i];
+            for (child, entry) in self.map.view() {
+                if entry.parent_pid == Some(parent) && !found.contains(&child) {
+                    found.push(child);
+                }
+            }
+            i += 1;
+        }
+        found.remove(0);
+
+        found
+    }
+
     pub fn export(&self) -> CanisterRegistryView {
         self.map.to_vec()
     }
 }
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdk::structures::DefaultMemoryImpl;
+
+    fn make_core() -> CanisterRegistryCore<DefaultMemoryImpl> {
+        let map = BTreeMap::init(DefaultMemoryImpl::default());
+        CanisterRegistryCore::new(map)
+    }
+
+    fn pid(n: u8) -> Principal {
+        Principal::from_slice(&[n])
+    }
+
+    fn insert(core: &mut CanisterRegistryCore<DefaultMemoryImpl>, n: u8, parent: Option<u8>) {
+        core.insert(
+            pid(n),
+            CanisterRegistryEntry {
+                canister_type: CanisterType::new("worker"),
+                parent_pid: parent.map(pid),
+                status: CanisterStatus::Installed,
+                module_hash: None,
+                created_at: 0,
+                cloned_from: None,
+            },
+        );
+    }
+
+    // 1 (root) -> 2 -> 3 -> 4, and 1 -> 5
+    fn make_tree() -> CanisterRegistryCore<DefaultMemoryImpl> {
+        let mut core = make_core();
+        insert(&mut core, 1, None);
+        insert(&mut core, 2, Some(1));
+        insert(&mut core, 3, Some(2));
+        insert(&mut core, 4, Some(3));
+        insert(&mut core, 5, Some(1));
+
+        core
+    }
+
+    #[test]
+    fn parents_are_root_first() {
+        let core = make_tree();
+
+        let parents: Vec<_> = core
+            .parents(pid(4))
+            .unwrap()
+            .into_iter()
+            .map(|p| p.principal)
+            .collect();
+        assert_eq!(parents, vec![pid(1), pid(2), pid(3)]);
+        assert!(core.parents(pid(1)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn descendants_walks_the_subtree() {
+        let core = make_tree();
+
+        assert_eq!(core.descendants(pid(2)), vec![pid(3), pid(4)]);
+        assert!(core.descendants(pid(5)).is_empty());
+        assert_eq!(core.descendants(pid(1)).len(), 4);
+    }
+
+    #[test]
+    fn set_parent_moves_the_subtree() {
+        let mut core = make_tree();
+        core.set_parent(pid(3), pid(5)).unwrap();
+
+        let parents: Vec<_> = core
+            .parents(pid(4))
+            .unwrap()
+            .into_iter()
+            .map(|p| p.principal)
+            .collect();
+        assert_eq!(parents, vec![pid(1), pid(5), pid(3)]);
+        assert!(core.descendants(pid(2)).is_empty());
+    }
+
+    #[test]
+    fn parent_loop_is_an_error() {
+        let mut core = make_tree();
+        core.set_parent(pid(2), pid(4)).unwrap();
+
+        let err = core.parents(pid(3)).unwrap_err().to_string();
+        assert!(err.contains("contains a loop"), "unexpected error: {err}");
+    }
+
+    #[test]
+    fn mark_clone_records_the_source() {
+        let mut core = make_tree();
+        insert(&mut core, 6, Some(2));
+
+        core.mark_clone(pid(6), pid(3)).unwrap();
+        assert_eq!(core.try_get(pid(6)).unwrap().module_hash, None);
+
+        // once loaded, the clone gets the source's module, whatever it was installed with
+        core.set_module_hash(pid(6), Some(vec![1, 2, 3])).unwrap();
+
+        let clone = core.try_get(pid(6)).unwrap();
+        assert_eq!(clone.cloned_from, Some(pid(3)));
+        assert_eq!(clone.module_hash, Some(vec![1, 2, 3]));
+        assert_eq!(clone.status, CanisterStatus::Installed);
+
+        // the source and the tree around it are untouched
+        assert_eq!(core.try_get(pid(3)).unwrap().cloned_from, None);
+        assert_eq!(core.descendants(pid(2)), vec![pid(3), pid(6), pid(4)]);
+        assert!(core.descendants(pid(6)).is_empty());
+    }
+
+    #[test]
+    fn mark_clone_needs_a_registered_clone() {
+        let mut core = make_tree();
+
+        assert!(core.mark_clone(pid(9), pid(3)).is_err());
+        assert!(core.set_module_hash(pid(9), None).is_err());
+        assert!(core.try_get(pid(9)).is_err());
+    }
+
+    #[test]
+    fn reparented_parents_match_set_parent() {
+        let core = make_tree();
+        let chains = core.reparented_parents(pid(3), pid(5)).unwrap();
+
+        // nothing moves until set_parent
+        assert_eq!(core.try_get(pid(3)).unwrap().parent_pid, Some(pid(2)));
+
+        let mut moved = make_tree();
+        moved.set_parent(pid(3), pid(5)).unwrap();
+
+        assert_eq!(chains.len(), 2);
+        for (child, chain) in chains {
+            let expected: Vec<_> = moved
+                .parents(child)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.principal)
+                .collect();
+            let chain: Vec<_> = chain.into_iter().map(|p| p.principal).collect();
+            assert_eq!(chain, expected, "chain of {child}");
+        }
+    }
+}
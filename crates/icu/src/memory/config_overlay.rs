@@ -0,0 +1,90 @@
+use crate::{
+    cdk::structures::{Cell, DefaultMemoryImpl, Memory, memory::VirtualMemory},
+    icu_register_memory, impl_storable_unbounded,
+    memory::CONFIG_OVERLAY_MEMORY_ID,
+};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+//
+// CONFIG_OVERLAY
+// root-authoritative, applied over the config baked into the wasm on every start
+//
+
+thread_local! {
+    pub static CONFIG_OVERLAY: RefCell<ConfigOverlayCore<VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(ConfigOverlayCore::new(Cell::init(
+            icu_register_memory!(CONFIG_OVERLAY_MEMORY_ID),
+            ConfigOverlayData::default(),
+        )));
+}
+
+///
+/// ConfigCommand
+///
+/// Replace(toml) : a whole config that's used instead of the baked-in one
+/// Patch(toml)   : tables are merged into the current overlay, anything else is replaced
+/// Reset         : back to the baked-in config
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum ConfigCommand {
+    Replace(String),
+    Patch(String),
+    Reset,
+}
+
+///
+/// ConfigOverlayData
+///
+/// toml    : the overlay, None for the baked-in config
+/// replace : the overlay is a whole config, the baked-in one is ignored
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ConfigOverlayData {
+    pub toml: Option<String>,
+    pub replace: bool,
+}
+
+impl_storable_unbounded!(ConfigOverlayData);
+
+///
+/// ConfigOverlay
+///
+
+pub struct ConfigOverlay;
+
+impl ConfigOverlay {
+    pub fn import(data: ConfigOverlayData) {
+        CONFIG_OVERLAY.with_borrow_mut(|core| core.import(data));
+    }
+
+    #[must_use]
+    pub fn export() -> ConfigOverlayData {
+        CONFIG_OVERLAY.with_borrow(ConfigOverlayCore::export)
+    }
+}
+
+///
+/// ConfigOverlayCore
+///
+
+pub struct ConfigOverlayCore<M: Memory> {
+    cell: Cell<ConfigOverlayData, M>,
+}
+
+impl<M: Memory> ConfigOverlayCore<M> {
+    pub const fn new(cell: Cell<ConfigOverlayData, M>) -> Self {
+        Self { cell }
+    }
+
+    pub fn import(&mut self, data: ConfigOverlayData) {
+        self.cell.set(data);
+    }
+
+    pub fn export(&self) -> ConfigOverlayData {
+        self.cell.get().clone()
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    Error, Log,
+    cdk::structures::{Cell, DefaultMemoryImpl, Memory, memory::VirtualMemory},
+    icu_register_memory, impl_storable_unbounded, log,
+    memory::{FEATURE_FLAGS_MEMORY_ID, MemoryError},
+};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
+use thiserror::Error as ThisError;
+
+//
+// FEATURE_FLAGS
+// root-authoritative, cascaded to every canister
+//
+
+thread_local! {
+    pub static FEATURE_FLAGS: RefCell<FeatureFlagsCore<VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(FeatureFlagsCore::new(Cell::init(
+            icu_register_memory!(FEATURE_FLAGS_MEMORY_ID),
+            FeatureFlagsData::default(),
+        )));
+}
+
+///
+/// FeatureFlagError
+///
+
+#[derive(Debug, ThisError)]
+pub enum FeatureFlagError {
+    #[error("feature flag names can't be empty")]
+    EmptyName,
+
+    #[error("feature flag {0} not found")]
+    NotFound(String),
+
+    #[error("percentage has to be between 0 and 100, got {0}")]
+    InvalidPercentage(u8),
+}
+
+///
+/// FeatureFlagCommand
+///
+/// Set(flag, percentage) : creates the flag if needed, 0 turns the rollout off
+/// Allow / Disallow      : principals that always get the flag, whatever the percentage
+/// Remove(flag)          : deletes the flag, is_enabled is false for everyone
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum FeatureFlagCommand {
+    Set(String, u8),
+    Allow(String, Vec<Principal>),
+    Disallow(String, Vec<Principal>),
+    Remove(String),
+}
+
+///
+/// FeatureFlag
+///
+/// percentage : share of principals that get the flag, picked by hashing the
+///              flag name with the principal so every flag gets a different share
+/// allowlist  : always enabled for these
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct FeatureFlag {
+    pub percentage: u8,
+    pub allowlist: BTreeSet<Principal>,
+}
+
+impl FeatureFlag {
+    #[must_use]
+    pub fn is_enabled(&self, flag: &str, pid: &Principal) -> bool {
+        match self.percentage {
+            0 => self.allowlist.contains(pid),
+            100.. => true,
+            pct => self.allowlist.contains(pid) || bucket(flag, pid) < pct,
+        }
+    }
+}
+
+// bucket
+// deterministic 0..100 for a flag and principal
+fn bucket(flag: &str, pid: &Principal) -> u8 {
+    let mut hasher = Sha256::new();
+    hasher.update(flag.as_bytes());
+    hasher.update([0]);
+    hasher.update(pid.as_slice());
+    let hash = hasher.finalize();
+
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+
+    // always below 100, so the cast is fine
+    #[allow(clippy::cast_possible_truncation)]
+    let bucket = (u64::from_be_bytes(bytes) % 100) as u8;
+
+    bucket
+}
+
+///
+/// FeatureFlagsData
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct FeatureFlagsData {
+    pub flags: BTreeMap<String, FeatureFlag>,
+}
+
+impl_storable_unbounded!(FeatureFlagsData);
+
+///
+/// FeatureFlags
+///
+
+pub struct FeatureFlags;
+
+impl FeatureFlags {
+    #[must_use]
+    pub fn is_enabled(flag: &str, pid: &Principal) -> bool {
+        FEATURE_FLAGS.with_borrow(|core| core.is_enabled(flag, pid))
+    }
+
+    pub fn command(cmd: FeatureFlagCommand) -> Result<(), Error> {
+        FEATURE_FLAGS.with_borrow_mut(|core| core.command(cmd))
+    }
+
+    pub fn import(data: FeatureFlagsData) {
+        FEATURE_FLAGS.with_borrow_mut(|core| core.import(data));
+    }
+
+    #[must_use]
+    pub fn export() -> FeatureFlagsData {
+        FEATURE_FLAGS.with_borrow(FeatureFlagsCore::export)
+    }
+}
+
+///
+/// FeatureFlagsCore
+///
+
+pub struct FeatureFlagsCore<M: Memory> {
+    cell: Cell<FeatureFlagsData, M>,
+}
+
+impl<M: Memory> FeatureFlagsCore<M> {
+    pub const fn new(cell: Cell<FeatureFlagsData, M>) -> Self {
+        Self { cell }
+    }
+
+    pub fn is_enabled(&self, flag: &str, pid: &Principal) -> bool {
+        self.cell
+            .get()
+            .flags
+            .get(flag)
+            .is_some_and(|f| f.is_enabled(flag, pid))
+    }
+
+    pub fn command(&mut self, cmd: FeatureFlagCommand) -> Result<(), Error> {
+        let mut data = self.cell.get().clone();
+
+        match cmd {
+            FeatureFlagCommand::Set(flag, percentage) => {
+                if percentage > 100 {
+                    Err(MemoryError::from(FeatureFlagError::InvalidPercentage(
+                        percentage,
+                    )))?;
+                }
+                Self::entry(&mut data, flag, true)?.percentage = percentage;
+            }
+            FeatureFlagCommand::Allow(flag, pids) => {
+                Self::entry(&mut data, flag, true)?.allowlist.extend(pids);
+            }
+            FeatureFlagCommand::Disallow(flag, pids) => {
+                let entry = Self::entry(&mut data, flag, false)?;
+                for pid in &pids {
+                    entry.allowlist.remove(pid);
+                }
+            }
+            FeatureFlagCommand::Remove(flag) => {
+                if data.flags.remove(&flag).is_none() {
+                    Err(MemoryError::from(FeatureFlagError::NotFound(flag)))?;
+                }
+            }
+        }
+
+        log!(Log::Ok, "feature_flags: {} flags", data.flags.len());
+        self.cell.set(data);
+
+        Ok(())
+    }
+
+    // entry
+    fn entry(
+        data: &mut FeatureFlagsData,
+        flag: String,
+        create: bool,
+    ) -> Result<&mut FeatureFlag, Error> {
+        if flag.is_empty() {
+            Err(MemoryError::from(FeatureFlagError::EmptyName))?;
+        }
+        if !create && !data.flags.contains_key(&flag) {
+            Err(MemoryError::from(FeatureFlagError::NotFound(flag.clone())))?;
+        }
+
+        Ok(data.flags.entry(flag).or_default())
+    }
+
+    pub fn import(&mut self, data: FeatureFlagsData) {
+        self.cell.set(data);
+    }
+
+    pub fn export(&self) -> FeatureFlagsData {
+        self.cell.get().clone()
+    }
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn core() -> FeatureFlagsCore<DefaultMemoryImpl> {
+        let cell = Cell::init(DefaultMemoryImpl::default(), FeatureFlagsData::default());
+        FeatureFlagsCore::new(cell)
+    }
+
+    fn pid(n: u16) -> Principal {
+        Principal::from_slice(&n.to_be_bytes())
+    }
+
+    #[test]
+    fn percentage_rollout() {
+        let mut core = core();
+        assert!(!core.is_enabled("trading", &pid(1)));
+
+        core.command(FeatureFlagCommand::Set("trading".into(), 25))
+            .unwrap();
+        let enabled = (0..1000)
+            .filter(|n| core.is_enabled("trading", &pid(*n)))
+            .count();
+        assert!((150..350).contains(&enabled), "{enabled} enabled");
+
+        // deterministic, and raising the percentage keeps everyone who had it
+        let before: Vec<_> = (0..1000)
+            .filter(|n| core.is_enabled("trading", &pid(*n)))
+            .collect();
+        core.command(FeatureFlagCommand::Set("trading".into(), 50))
+            .unwrap();
+        assert!(before.iter().all(|n| core.is_enabled("trading", &pid(*n))));
+
+        core.command(FeatureFlagCommand::Set("trading".into(), 100))
+            .unwrap();
+        assert!((0..1000).all(|n| core.is_enabled("trading", &pid(n))));
+
+        assert!(
+            core.command(FeatureFlagCommand::Set("trading".into(), 101))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn allowlist_and_remove() {
+        let mut core = core();
+
+        core.command(FeatureFlagCommand::Allow("beta".into(), vec![pid(1)]))
+            .unwrap();
+        assert!(core.is_enabled("beta", &pid(1)));
+        assert!(!core.is_enabled("beta", &pid(2)));
+
+        core.command(FeatureFlagCommand::Disallow("beta".into(), vec![pid(1)]))
+            .unwrap();
+        assert!(!core.is_enabled("beta", &pid(1)));
+
+        core.command(FeatureFlagCommand::Remove("beta".into()))
+            .unwrap();
+        assert!(
+            core.command(FeatureFlagCommand::Remove("beta".into()))
+                .is_err()
+        );
+        assert!(
+            core.command(FeatureFlagCommand::Disallow("beta".into(), vec![]))
+                .is_err()
+        );
+    }
+}
@@ -1,9 +1,15 @@
 pub mod app_state;
+pub mod audit_log;
 pub mod canister;
+pub mod config_overlay;
 pub mod cycle_tracker;
+pub mod feature_flags;
 pub mod memory_registry;
+pub mod role_registry;
+pub mod whitelist;
 
 pub use app_state::{AppState, AppStateData};
+pub use audit_log::{AuditLog, AuditLogPage};
 pub use canister::{
     children::{CanisterChildren, CanisterChildrenView},
     directory::{CanisterDirectory, CanisterDirectoryView},
@@ -11,8 +17,12 @@ pub use canister::{
     registry::{CanisterRegistry, CanisterRegistryView},
     state::{CanisterState, CanisterStateData},
 };
+pub use config_overlay::{ConfigOverlay, ConfigOverlayData};
 pub use cycle_tracker::{CycleTracker, CycleTrackerView};
+pub use feature_flags::{FeatureFlags, FeatureFlagsData};
 pub use memory_registry::MemoryRegistry;
+pub use role_registry::{RoleRegistry, RoleRegistryView};
+pub use whitelist::{Whitelist, WhitelistData};
 
 use crate::{
     cdk::structures::{DefaultMemoryImpl, memory::MemoryManager},
@@ -22,7 +32,10 @@ use crate::{
             children::CanisterChildrenError, directory::CanisterDirectoryError,
             registry::CanisterRegistryError, state::CanisterStateError,
         },
+        feature_flags::FeatureFlagError,
         memory_registry::MemoryRegistryError,
+        role_registry::RoleRegistryError,
+        whitelist::WhitelistError,
     },
 };
 use std::cell::RefCell;
@@ -41,6 +54,10 @@ pub(crate) const CANISTER_REGISTRY_MEMORY_ID: u8 = 2;
 // root-authoritative (cascaded to subnet)
 pub(crate) const APP_STATE_MEMORY_ID: u8 = 3;
 pub(crate) const CANISTER_DIRECTORY_MEMORY_ID: u8 = 4;
+pub(crate) const ROLE_REGISTRY_MEMORY_ID: u8 = 7;
+pub(crate) const WHITELIST_MEMORY_ID: u8 = 8;
+pub(crate) const FEATURE_FLAGS_MEMORY_ID: u8 = 9;
+pub(crate) const CONFIG_OVERLAY_MEMORY_ID: u8 = 12;
 
 // all
 pub(crate) const CANISTER_STATE_MEMORY_ID: u8 = 5;
@@ -48,6 +65,7 @@ pub(crate) const CANISTER_CHILDREN_MEMORY_ID: u8 = 6;
 
 // trackers (all)
 pub(crate) const CYCLE_TRACKER_MEMORY_ID: u8 = 10;
+pub(crate) const AUDIT_LOG_MEMORY_ID: u8 = 11;
 
 //
 // MEMORY_MANAGER
@@ -84,6 +102,15 @@ pub enum MemoryError {
     #[error(transparent)]
     CanisterStateError(#[from] CanisterStateError),
 
+    #[error(transparent)]
+    FeatureFlagError(#[from] FeatureFlagError),
+
     #[error(transparent)]
     MemoryRegistryError(#[from] MemoryRegistryError),
+
+    #[error(transparent)]
+    RoleRegistryError(#[from] RoleRegistryError),
+
+    #[error(transparent)]
+    WhitelistError(#[from] WhitelistError),
 }
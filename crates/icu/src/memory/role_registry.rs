@@ -0,0 +1,198 @@
+use crate::{
+    Error,
+    cdk::structures::{BTreeMap, DefaultMemoryImpl, Memory, memory::VirtualMemory},
+    icu_register_memory, impl_storable_unbounded,
+    memory::{MemoryError, ROLE_REGISTRY_MEMORY_ID},
+};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use thiserror::Error as ThisError;
+
+//
+// ROLE_REGISTRY
+// role name -> principals
+//
+
+thread_local! {
+    pub static ROLE_REGISTRY: RefCell<RoleRegistryCore<VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(RoleRegistryCore::new(BTreeMap::init(
+            icu_register_memory!(ROLE_REGISTRY_MEMORY_ID),
+        )));
+}
+
+///
+/// RoleRegistryError
+///
+
+#[derive(Debug, ThisError)]
+pub enum RoleRegistryError {
+    #[error("role name can't be empty")]
+    EmptyRole,
+
+    #[error("principal {1} does not have role '{0}'")]
+    NotGranted(String, Principal),
+}
+
+///
+/// RoleEntry
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RoleEntry {
+    pub principals: Vec<Principal>,
+}
+
+impl_storable_unbounded!(RoleEntry);
+
+///
+/// RoleRegistry
+///
+
+pub type RoleRegistryView = Vec<(String, RoleEntry)>;
+
+pub struct RoleRegistry;
+
+impl RoleRegistry {
+    #[must_use]
+    pub fn get(role: &str) -> Option<RoleEntry> {
+        ROLE_REGISTRY.with_borrow(|core| core.get(role))
+    }
+
+    #[must_use]
+    pub fn has_role(role: &str, pid: &Principal) -> bool {
+        ROLE_REGISTRY.with_borrow(|core| core.has_role(role, pid))
+    }
+
+    pub fn grant(role: &str, pid: Principal) -> Result<(), Error> {
+        ROLE_REGISTRY.with_borrow_mut(|core| core.grant(role, pid))
+    }
+
+    pub fn revoke(role: &str, pid: Principal) -> Result<(), Error> {
+        ROLE_REGISTRY.with_borrow_mut(|core| core.revoke(role, pid))
+    }
+
+    pub fn import(view: RoleRegistryView) {
+        ROLE_REGISTRY.with_borrow_mut(|core| core.import(view));
+    }
+
+    #[must_use]
+    pub fn export() -> RoleRegistryView {
+        ROLE_REGISTRY.with_borrow(RoleRegistryCore::export)
+    }
+}
+
+///
+/// RoleRegistryCore
+///
+
+pub struct RoleRegistryCore<M: Memory> {
+    map: BTreeMap<String, RoleEntry, M>,
+}
+
+impl<M: Memory> RoleRegistryCore<M> {
+    pub const fn new(map: BTreeMap<String, RoleEntry, M>) -> Self {
+        Self { map }
+    }
+
+    pub fn get(&self, role: &str) -> Option<RoleEntry> {
+        self.map.get(&role.to_string())
+    }
+
+    pub fn has_role(&self, role: &str, pid: &Principal) -> bool {
+        self.get(role)
+            .is_some_and(|entry| entry.principals.contains(pid))
+    }
+
+    pub fn grant(&mut self, role: &str, pid: Principal) -> Result<(), Error> {
+        if role.is_empty() {
+            Err(MemoryError::from(RoleRegistryError::EmptyRole))?;
+        }
+
+        let mut entry = self.get(role).unwrap_or_default();
+
+        if !entry.principals.contains(&pid) {
+            entry.principals.push(pid);
+            self.map.insert(role.to_string(), entry);
+        }
+
+        Ok(())
+    }
+
+    pub fn revoke(&mut self, role: &str, pid: Principal) -> Result<(), Error> {
+        let Some(mut entry) = self.get(role).filter(|e| e.principals.contains(&pid)) else {
+            Err(MemoryError::from(RoleRegistryError::NotGranted(
+                role.to_string(),
+                pid,
+            )))?
+        };
+
+        entry.principals.retain(|p| p != &pid);
+
+        if entry.principals.is_empty() {
+            self.map.remove(&role.to_string());
+        } else {
+            self.map.insert(role.to_string(), entry);
+        }
+
+        Ok(())
+    }
+
+    pub fn import(&mut self, view: RoleRegistryView) {
+        self.map.clear();
+        for (k, v) in view {
+            self.map.insert(k, v);
+        }
+    }
+
+    pub fn export(&self) -> RoleRegistryView {
+        self.map.to_vec()
+    }
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdk::structures::DefaultMemoryImpl;
+
+    fn make_core() -> RoleRegistryCore<DefaultMemoryImpl> {
+        let map = BTreeMap::init(DefaultMemoryImpl::default());
+        RoleRegistryCore::new(map)
+    }
+
+    fn pid(n: u8) -> Principal {
+        Principal::from_slice(&[n])
+    }
+
+    #[test]
+    fn grant_and_revoke() {
+        let mut core = make_core();
+
+        core.grant("moderator", pid(1)).unwrap();
+        core.grant("moderator", pid(1)).unwrap();
+        core.grant("moderator", pid(2)).unwrap();
+
+        assert!(core.has_role("moderator", &pid(1)));
+        assert!(!core.has_role("admin", &pid(1)));
+        assert_eq!(core.get("moderator").unwrap().principals.len(), 2);
+
+        core.revoke("moderator", pid(1)).unwrap();
+        assert!(!core.has_role("moderator", &pid(1)));
+
+        // last principal removes the role entirely
+        core.revoke("moderator", pid(2)).unwrap();
+        assert!(core.export().is_empty());
+    }
+
+    #[test]
+    fn revoke_missing_fails() {
+        let mut core = make_core();
+
+        assert!(core.revoke("moderator", pid(1)).is_err());
+        assert!(core.grant("", pid(1)).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{
+    Error, Log,
+    cdk::structures::{Cell, DefaultMemoryImpl, Memory, memory::VirtualMemory},
+    config::Config,
+    icu_register_memory, impl_storable_unbounded, log,
+    memory::{MemoryError, WHITELIST_MEMORY_ID},
+};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::BTreeSet};
+use thiserror::Error as ThisError;
+
+//
+// WHITELIST
+// root-authoritative, the config only seeds it the first time root starts
+//
+
+thread_local! {
+    pub static WHITELIST: RefCell<WhitelistCore<VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(WhitelistCore::new(Cell::init(
+            icu_register_memory!(WHITELIST_MEMORY_ID),
+            WhitelistData::default(),
+        )));
+}
+
+///
+/// WhitelistError
+///
+
+#[derive(Debug, ThisError)]
+pub enum WhitelistError {
+    #[error("whitelist is already {0}")]
+    AlreadyInState(&'static str),
+}
+
+///
+/// WhitelistCommand
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum WhitelistCommand {
+    Add(Vec<Principal>),
+    Remove(Vec<Principal>),
+    Enable,
+    Disable,
+}
+
+///
+/// WhitelistData
+///
+/// enabled : when false everyone passes is_whitelisted
+/// seeded  : the config has been copied in, so it's never done again
+///
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct WhitelistData {
+    pub enabled: bool,
+    pub seeded: bool,
+    pub principals: BTreeSet<Principal>,
+}
+
+impl_storable_unbounded!(WhitelistData);
+
+///
+/// Whitelist
+///
+
+pub struct Whitelist;
+
+impl Whitelist {
+    #[must_use]
+    pub fn is_allowed(pid: &Principal) -> bool {
+        WHITELIST.with_borrow(|core| core.is_allowed(pid))
+    }
+
+    pub fn command(cmd: WhitelistCommand) -> Result<(), Error> {
+        WHITELIST.with_borrow_mut(|core| core.command(cmd))
+    }
+
+    // seed_from_config
+    // root only, a config whitelist turns the whitelist on with those principals
+    pub fn seed_from_config() {
+        let config = Config::try_get().ok();
+
+        let principals = config
+            .as_ref()
+            .and_then(|c| c.whitelist.as_ref())
+            .map(|list| {
+                list.principals
+                    .iter()
+                    .filter_map(|s| Principal::from_text(s).ok())
+                    .collect()
+            });
+
+        WHITELIST.with_borrow_mut(|core| core.seed(principals));
+    }
+
+    pub fn import(data: WhitelistData) {
+        WHITELIST.with_borrow_mut(|core| core.import(data));
+    }
+
+    #[must_use]
+    pub fn export() -> WhitelistData {
+        WHITELIST.with_borrow(WhitelistCore::export)
+    }
+}
+
+///
+/// WhitelistCore
+///
+
+pub struct WhitelistCore<M: Memory> {
+    cell: Cell<WhitelistData, M>,
+}
+
+impl<M: Memory> WhitelistCore<M> {
+    pub const fn new(cell: Cell<WhitelistData, M>) -> Self {
+        Self { cell }
+    }
+
+    pub fn is_allowed(&self, pid: &Principal) -> bool {
+        let data = self.cell.get();
+
+        !data.enabled || data.principals.contains(pid)
+    }
+
+    pub fn command(&mut self, cmd: WhitelistCommand) -> Result<(), Error> {
+        let mut data = self.cell.get().clone();
+
+        match cmd {
+            WhitelistCommand::Add(pids) => data.principals.extend(pids),
+            WhitelistCommand::Remove(pids) => {
+                for pid in &pids {
+                    data.principals.remove(pid);
+                }
+            }
+            WhitelistCommand::Enable => {
+                if data.enabled {
+                    Err(MemoryError::from(WhitelistError::AlreadyInState("enabled")))?;
+                }
+                data.enabled = true;
+            }
+            WhitelistCommand::Disable => {
+                if !data.enabled {
+                    Err(MemoryError::from(WhitelistError::AlreadyInState(
+                        "disabled",
+                    )))?;
+                }
+                data.enabled = false;
+            }
+        }
+
+        log!(
+            Log::Ok,
+            "whitelist: enabled {}, {} principals",
+            data.enabled,
+            data.principals.len()
+        );
+        self.cell.set(data);
+
+        Ok(())
+    }
+
+    pub fn seed(&mut self, principals: Option<BTreeSet<Principal>>) {
+        let mut data = self.cell.get().clone();
+        if data.seeded {
+            return;
+        }
+
+        if let Some(principals) = principals {
+            data.enabled = true;
+            data.principals = principals;
+        }
+        data.seeded = true;
+
+        self.cell.set(data);
+    }
+
+    pub fn import(&mut self, data: WhitelistData) {
+        self.cell.set(data);
+    }
+
+    pub fn export(&self) -> WhitelistData {
+        self.cell.get().clone()
+    }
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn core() -> WhitelistCore<DefaultMemoryImpl> {
+        let cell = Cell::init(DefaultMemoryImpl::default(), WhitelistData::default());
+        WhitelistCore::new(cell)
+    }
+
+    fn pid(n: u8) -> Principal {
+        Principal::from_slice(&[n])
+    }
+
+    #[test]
+    fn disabled_allows_everyone() {
+        let core = core();
+
+        assert!(core.is_allowed(&pid(1)));
+    }
+
+    #[test]
+    fn commands() {
+        let mut core = core();
+
+        core.command(WhitelistCommand::Add(vec![pid(1), pid(2)]))
+            .unwrap();
+        core.command(WhitelistCommand::Enable).unwrap();
+        assert!(core.command(WhitelistCommand::Enable).is_err());
+
+        assert!(core.is_allowed(&pid(1)));
+        assert!(!core.is_allowed(&pid(3)));
+
+        core.command(WhitelistCommand::Remove(vec![pid(1)]))
+            .unwrap();
+        assert!(!core.is_allowed(&pid(1)));
+    }
+
+    #[test]
+    fn seed_only_once() {
+        let mut core = core();
+
+        core.seed(Some([pid(1)].into()));
+        assert!(core.is_allowed(&pid(1)));
+        assert!(!core.is_allowed(&pid(2)));
+
+        // runtime edits survive a second seed
+        core.command(WhitelistCommand::Add(vec![pid(2)])).unwrap();
+        core.seed(Some([pid(1)].into()));
+        assert!(core.is_allowed(&pid(2)));
+    }
+}
@@ -2,31 +2,95 @@ use crate::{
     Error,
     cdk::mgmt::CanisterInstallMode,
     config::Config,
-    interface::{ic::install_code, prelude::*},
+    interface::{
+        ic::{canister_status, install_code},
+        prelude::*,
+    },
     memory::{
-        CanisterDirectory, CanisterPool, CanisterRegistry, CanisterState, canister::CanisterEntry,
+        CanisterChildren, CanisterDirectory, CanisterPool, CanisterRegistry, CanisterState,
+        CanisterStateData, canister::CanisterEntry,
     },
     ops::{
+        pool::verify_pool_canister,
         prelude::*,
         state::{StateBundle, cascade, update_canister},
     },
-    state::wasm::WasmRegistry,
+    state::{
+        pool::{PoolAction, PoolReport},
+        wasm::WasmRegistry,
+    },
 };
+use thiserror::Error as ThisError;
+
+///
+/// CanisterError
+///
+
+#[derive(Debug, ThisError)]
+pub enum CanisterError {
+    #[error("canister {0} is already managed by root")]
+    AlreadyManaged(Principal),
+
+    #[error("canister {1} can't become the parent of {0}")]
+    InvalidParent(Principal, Principal),
+
+    #[error("canister {0} has no module installed")]
+    NoModuleInstalled(Principal),
+
+    #[error("canister {0} isn't running ICU: {1}")]
+    NotIcu(Principal, String),
+
+    #[error("root is not a controller of canister {0}")]
+    RootNotController(Principal),
+
+    #[error("canister {0} is a {1:?}, not a {2}")]
+    TypeMismatch(Principal, Option<CanisterType>, CanisterType),
+}
+
+///
+/// AdoptTarget
+/// where an externally created canister should end up
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub enum AdoptTarget {
+    Canister(CanisterType),
+    Pool,
+}
 
 ///
 /// allocate_canister
-/// firstly looks in the pool to find a canister
+/// firstly looks in the pool to find a canister, anything that fails
+/// verification is quarantined and we move on to the next one. If we couldn't
+/// check a canister at all it goes back into the pool untouched
 ///
 async fn allocate_canister(ty: &CanisterType) -> Result<(Principal, Cycles), Error> {
+    let canister = Config::try_get_canister(ty)?;
+
     // try pool first
-    if let Some((pid, entry)) = CanisterPool::pop_first() {
-        log!(Log::Ok, "⚡ reusing {pid} from pool ({entry:?})");
+    while let Some((pid, entry)) = CanisterPool::pop_first() {
+        match verify_pool_canister(pid, canister.initial_cycles).await {
+            Ok(Ok(cycles)) => {
+                log!(Log::Ok, "⚡ reusing {pid} from pool ({cycles})");
+
+                return Ok((pid, cycles));
+            }
+            Ok(Err(e)) => {
+                log!(Log::Warn, "⚡ quarantining pool canister {pid}: {e}");
 
-        return Ok((pid, entry.cycles));
+                PoolReport::record(pid, PoolAction::Quarantined(e.to_string()));
+                CanisterPool::quarantine(pid, entry, e.to_string());
+            }
+            Err(e) => {
+                PoolReport::record(pid, PoolAction::Failed(e.to_string()));
+                CanisterPool::restore(pid, entry);
+
+                return Err(e);
+            }
+        }
     }
 
     // fallback: fresh canister
-    let canister = Config::try_get_canister(ty)?;
     let cycles = canister.initial_cycles;
     let pid = create_canister(cycles).await?;
 
@@ -175,3 +239,224 @@ pub(super) async fn register_installed(
 
     Ok(())
 }
+
+///
+/// adopt_canister
+/// takes over a canister that was created outside of ICU (dfx, cycles ledger...)
+/// root has to be a controller, and it becomes the adopted canister's parent
+///
+/// Pool : has to pass the same checks as a pool canister being reused
+/// Canister : has to be running ICU as that type, it then gets its parents and
+/// state from root like a canister root created itself
+///
+pub async fn adopt_canister(canister_pid: Principal, target: AdoptTarget) -> Result<(), Error> {
+    if !CanisterState::is_root() {
+        Err(OpsError::NotRoot)?;
+    }
+
+    if CanisterRegistry::get(canister_pid).is_some() || CanisterPool::get(&canister_pid).is_some() {
+        Err(OpsError::from(CanisterError::AlreadyManaged(canister_pid)))?;
+    }
+
+    let status = canister_status(canister_pid).await?;
+    if !status.settings.controllers.contains(&canister_self()) {
+        Err(OpsError::from(CanisterError::RootNotController(
+            canister_pid,
+        )))?;
+    }
+
+    match target {
+        AdoptTarget::Pool => {
+            let cycles = verify_pool_canister(canister_pid, Cycles::default())
+                .await?
+                .map_err(OpsError::from)?;
+
+            CanisterPool::register(canister_pid, cycles);
+            PoolReport::record(canister_pid, PoolAction::MovedIn(cycles));
+
+            log!(
+                Log::Ok,
+                "⚡ adopt_canister: {canister_pid} (pool, {cycles})"
+            );
+        }
+
+        AdoptTarget::Canister(canister_type) => {
+            let canister = Config::try_get_canister(&canister_type)?;
+            let module_hash =
+                status
+                    .module_hash
+                    .ok_or(OpsError::from(CanisterError::NoModuleInstalled(
+                        canister_pid,
+                    )))?;
+
+            let state = canister_state(canister_pid).await?;
+            if state.canister_type.as_ref() != Some(&canister_type) {
+                Err(OpsError::from(CanisterError::TypeMismatch(
+                    canister_pid,
+                    state.canister_type,
+                    canister_type.clone(),
+                )))?;
+            }
+
+            CanisterRegistry::adopt(canister_pid, &canister_type, canister_self(), module_hash);
+            CanisterChildren::insert(canister_pid, canister_type.clone());
+            if canister.uses_directory {
+                CanisterDirectory::insert(canister_type.clone(), canister_pid)?;
+            }
+
+            if let Err(e) = sync_adopted(canister_pid).await {
+                // nothing has cascaded yet, so dropping the local entries is enough
+                let _ = CanisterRegistry::remove(&canister_pid);
+                CanisterChildren::remove(&canister_pid);
+                if canister.uses_directory {
+                    let _ = CanisterDirectory::remove(&canister_type, canister_pid);
+                }
+
+                return Err(e);
+            }
+
+            if canister.uses_directory {
+                cascade(&StateBundle::canister_directory()).await?;
+            }
+
+            log!(
+                Log::Ok,
+                "⚡ adopt_canister: {canister_pid} ({canister_type})"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// canister_state
+// also tells us the canister is running ICU at all
+async fn canister_state(pid: Principal) -> Result<CanisterStateData, Error> {
+    let res = Call::unbounded_wait(pid, "icu_canister_state")
+        .await
+        .map_err(|e| OpsError::from(CanisterError::NotIcu(pid, e.to_string())))?;
+
+    Ok(res
+        .candid::<CanisterStateData>()
+        .map_err(|e| OpsError::from(CanisterError::NotIcu(pid, e.to_string())))?)
+}
+
+// sync_adopted
+// sends an adopted canister its parent chain and everything in root's state
+async fn sync_adopted(pid: Principal) -> Result<(), Error> {
+    update_parents(pid, CanisterRegistry::parents(pid)?).await?;
+    update_canister(&pid, &StateBundle::all()).await
+}
+
+///
+/// reparent_canister
+/// moves a canister (and everything below it) under a new parent
+///
+/// the new parent learns about its child first, then every canister in the moved
+/// subtree gets its new parent chain and the old parent lets go.  Root's registry
+/// is only updated once all of that worked, so a failed reparent can be retried
+///
+pub async fn reparent_canister(
+    canister_pid: Principal,
+    new_parent_pid: Principal,
+) -> Result<(), Error> {
+    if !CanisterState::is_root() {
+        Err(OpsError::NotRoot)?;
+    }
+
+    let entry = CanisterRegistry::try_get(canister_pid)?;
+    let Some(old_parent_pid) = entry.parent_pid else {
+        // only root has no parent
+        return Err(OpsError::from(CanisterError::InvalidParent(
+            canister_pid,
+            new_parent_pid,
+        )))?;
+    };
+
+    // the new parent can't be the canister itself, or anything below it
+    CanisterRegistry::try_get(new_parent_pid)?;
+    if new_parent_pid == old_parent_pid
+        || new_parent_pid == canister_pid
+        || CanisterRegistry::descendants(canister_pid).contains(&new_parent_pid)
+    {
+        Err(OpsError::from(CanisterError::InvalidParent(
+            canister_pid,
+            new_parent_pid,
+        )))?;
+    }
+
+    // parent chains, top down
+    let chains = CanisterRegistry::reparented_parents(canister_pid, new_parent_pid)?;
+
+    update_children(
+        new_parent_pid,
+        canister_pid,
+        Some(entry.canister_type.clone()),
+    )
+    .await?;
+    for (pid, parents) in chains {
+        update_parents(pid, parents).await?;
+    }
+    update_children(old_parent_pid, canister_pid, None).await?;
+
+    // registry
+    CanisterRegistry::set_parent(canister_pid, new_parent_pid)?;
+
+    log!(
+        Log::Ok,
+        "⚡ reparent_canister: {canister_pid} ({}) {old_parent_pid} -> {new_parent_pid}",
+        entry.canister_type,
+    );
+
+    Ok(())
+}
+
+// update_children
+// inserts (Some) or removes (None) a child on a parent, locally if it's root
+pub(super) async fn update_children(
+    parent_pid: Principal,
+    child_pid: Principal,
+    child_type: Option<CanisterType>,
+) -> Result<(), Error> {
+    if parent_pid == canister_self() {
+        match child_type {
+            Some(ty) => CanisterChildren::insert(child_pid, ty),
+            None => CanisterChildren::remove(&child_pid),
+        }
+
+        return Ok(());
+    }
+
+    let res = Call::unbounded_wait(parent_pid, "icu_canister_children_update")
+        .with_args(&(child_pid, child_type))
+        .await
+        .map_err(InterfaceError::from)?;
+
+    res.candid::<Result<(), Error>>()
+        .map_err(InterfaceError::from)?
+}
+
+// reset_children
+// clears CanisterChildren on a canister, ie. a clone that loaded its source's memory
+pub(super) async fn reset_children(pid: Principal) -> Result<(), Error> {
+    let res = Call::unbounded_wait(pid, "icu_canister_children_reset")
+        .await
+        .map_err(InterfaceError::from)?;
+
+    res.candid::<Result<(), Error>>()
+        .map_err(InterfaceError::from)?
+}
+
+// update_parents
+pub(super) async fn update_parents(
+    pid: Principal,
+    parents: Vec<CanisterEntry>,
+) -> Result<(), Error> {
+    let res = Call::unbounded_wait(pid, "icu_canister_parents_update")
+        .with_arg(parents)
+        .await
+        .map_err(InterfaceError::from)?;
+
+    res.candid::<Result<(), Error>>()
+        .map_err(InterfaceError::from)?
+}
@@ -0,0 +1,283 @@
+use crate::{
+    Error,
+    cdk::{
+        mgmt::SnapshotId,
+        mgmt_types::{SnapshotDataKind, SnapshotDataOffset, UploadCanisterSnapshotMetadataArgs},
+    },
+    interface::ic::{
+        delete_canister_snapshot, load_canister_snapshot, read_canister_snapshot_data,
+        read_canister_snapshot_metadata, start_canister, stop_canister, take_canister_snapshot,
+        upload_canister_snapshot_data, upload_canister_snapshot_metadata,
+    },
+    memory::{
+        CanisterDirectory, CanisterRegistry, CanisterState,
+        canister::{
+            CanisterEntry,
+            registry::{CanisterRegistryEntry, CanisterStatus},
+        },
+    },
+    ops::{
+        canister::{create_and_install_canister, reset_children, update_children, update_parents},
+        lifecycle::{CanisterCommand, canister_command},
+        pool::move_canister_to_pool,
+        prelude::*,
+        state::{StateBundle, cascade, update_canister},
+    },
+};
+use thiserror::Error as ThisError;
+
+///
+/// CloneError
+///
+
+#[derive(Debug, ThisError)]
+pub enum CloneError {
+    #[error("the root canister can't be cloned")]
+    RootNotAllowed,
+
+    #[error("canister {0} can't be cloned while {1:?}")]
+    InvalidStatus(Principal, CanisterStatus),
+}
+
+///
+/// Constants
+///
+
+// read_canister_snapshot_data replies have to fit in a single message
+const SNAPSHOT_CHUNK_SIZE: u64 = 1024 * 1024;
+
+///
+/// clone_canister
+/// creates a new canister of the same type and parent as the source, then
+/// replays a snapshot of the source into it so it starts with the same state
+///
+pub async fn clone_canister(source_pid: Principal) -> Result<Principal, Error> {
+    if !CanisterState::is_root() {
+        Err(OpsError::NotRoot)?;
+    }
+    if source_pid == canister_self() {
+        Err(OpsError::from(CloneError::RootNotAllowed))?;
+    }
+
+    let source = CanisterRegistry::try_get(source_pid)?;
+    if source.status != CanisterStatus::Installed {
+        Err(OpsError::from(CloneError::InvalidStatus(
+            source_pid,
+            source.status.clone(),
+        )))?;
+    }
+    let parents = CanisterRegistry::parents(source_pid)?;
+
+    // snapshot the source, drained and stopped so the memory is consistent
+    canister_command(source_pid, CanisterCommand::Stop).await?;
+    let snapshot = take_canister_snapshot(source_pid).await;
+    canister_command(source_pid, CanisterCommand::Start).await?;
+    let snapshot_id = snapshot?.id;
+
+    // create the clone the usual way so it goes through all the bookkeeping,
+    // then load the snapshot into it
+    let res = match create_and_install_canister(&source.canister_type, &parents, None).await {
+        Ok(clone_pid) => build_clone(&source, source_pid, clone_pid, &parents, &snapshot_id)
+            .await
+            .map(|()| clone_pid)
+            .map_err(|e| (Some(clone_pid), e)),
+        Err(e) => Err((None, e)),
+    };
+
+    // the snapshot goes whatever happened, a canister only gets a few of them
+    let _ = delete_canister_snapshot(source_pid, snapshot_id).await;
+
+    let clone_pid = match res {
+        Ok(clone_pid) => clone_pid,
+        Err((clone_pid, e)) => {
+            if let Some(clone_pid) = clone_pid {
+                discard_clone(clone_pid, source_pid, &source, &parents).await;
+            }
+            return Err(e);
+        }
+    };
+
+    log!(
+        Log::Ok,
+        "🐑 clone_canister: {source_pid} -> {clone_pid} ({})",
+        source.canister_type
+    );
+
+    Ok(clone_pid)
+}
+
+// build_clone
+// everything after the clone is created, on error the caller discards it
+async fn build_clone(
+    source: &CanisterRegistryEntry,
+    source_pid: Principal,
+    clone_pid: Principal,
+    parents: &[CanisterEntry],
+    snapshot_id: &SnapshotId,
+) -> Result<(), Error> {
+    CanisterRegistry::mark_clone(clone_pid, source_pid)?;
+
+    if let Some(parent) = parents.last() {
+        update_children(
+            parent.principal,
+            clone_pid,
+            Some(source.canister_type.clone()),
+        )
+        .await?;
+    }
+
+    // replay the snapshot into the clone
+    replay_snapshot(source_pid, snapshot_id.clone(), clone_pid).await?;
+    CanisterRegistry::set_module_hash(clone_pid, source.module_hash.clone())?;
+
+    // the loaded memory is the source's, so bring it back in line with root:
+    // a fresh clone has no children, and its parents and state come from root
+    reset_children(clone_pid).await?;
+    update_parents(clone_pid, parents.to_vec()).await?;
+    update_canister(&clone_pid, &StateBundle::all()).await?;
+
+    Ok(())
+}
+
+// discard_clone
+// takes a half-built clone out of the tree and moves it to the pool.  If that
+// fails too it stays in the registry with cloned_from set, so it can be found
+// and removed with icu_move_canister_to_pool
+async fn discard_clone(
+    clone_pid: Principal,
+    source_pid: Principal,
+    source: &CanisterRegistryEntry,
+    parents: &[CanisterEntry],
+) {
+    let res = async {
+        if let Some(parent) = parents.last() {
+            update_children(parent.principal, clone_pid, None).await?;
+        }
+        if CanisterDirectory::get(&source.canister_type)
+            .is_some_and(|entry| entry.canisters.contains(&clone_pid))
+        {
+            CanisterDirectory::remove(&source.canister_type, clone_pid)?;
+            cascade(&StateBundle::canister_directory()).await?;
+        }
+
+        move_canister_to_pool(clone_pid).await
+    }
+    .await;
+
+    match res {
+        Ok(()) => log!(
+            Log::Warn,
+            "🐑 clone_canister: discarded half-built clone {clone_pid} of {source_pid}"
+        ),
+        Err(e) => log!(
+            Log::Error,
+            "🐑 clone_canister: half-built clone {clone_pid} of {source_pid} is still registered: {e}"
+        ),
+    }
+}
+
+// replay_snapshot
+// downloads the source snapshot, uploads it to the clone and loads it
+async fn replay_snapshot(
+    source_pid: Principal,
+    source_snapshot_id: SnapshotId,
+    clone_pid: Principal,
+) -> Result<(), Error> {
+    let metadata = read_canister_snapshot_metadata(source_pid, source_snapshot_id.clone()).await?;
+
+    let snapshot_id = upload_canister_snapshot_metadata(UploadCanisterSnapshotMetadataArgs {
+        canister_id: clone_pid,
+        replace_snapshot: None,
+        wasm_module_size: metadata.wasm_module_size,
+        globals: metadata.globals,
+        wasm_memory_size: metadata.wasm_memory_size,
+        stable_memory_size: metadata.stable_memory_size,
+        certified_data: metadata.certified_data,
+        global_timer: metadata.global_timer,
+        on_low_wasm_memory_hook_status: metadata.on_low_wasm_memory_hook_status,
+    })
+    .await?;
+
+    let res = async {
+        for (section, total) in [
+            (Section::WasmModule, metadata.wasm_module_size),
+            (Section::MainMemory, metadata.wasm_memory_size),
+            (Section::StableMemory, metadata.stable_memory_size),
+        ] {
+            let mut offset = 0;
+
+            while offset < total {
+                let size = SNAPSHOT_CHUNK_SIZE.min(total - offset);
+                let chunk = read_canister_snapshot_data(
+                    source_pid,
+                    source_snapshot_id.clone(),
+                    section.kind(offset, size),
+                )
+                .await?;
+                upload_canister_snapshot_data(
+                    clone_pid,
+                    snapshot_id.clone(),
+                    section.offset(offset),
+                    chunk,
+                )
+                .await?;
+
+                offset += size;
+            }
+        }
+
+        for chunk in metadata.wasm_chunk_store {
+            let data = read_canister_snapshot_data(
+                source_pid,
+                source_snapshot_id.clone(),
+                SnapshotDataKind::WasmChunk { hash: chunk.hash },
+            )
+            .await?;
+            upload_canister_snapshot_data(
+                clone_pid,
+                snapshot_id.clone(),
+                SnapshotDataOffset::WasmChunk,
+                data,
+            )
+            .await?;
+        }
+
+        stop_canister(clone_pid).await?;
+        load_canister_snapshot(clone_pid, snapshot_id.clone()).await?;
+        start_canister(clone_pid).await?;
+
+        Ok::<(), Error>(())
+    }
+    .await;
+
+    let _ = delete_canister_snapshot(clone_pid, snapshot_id).await;
+
+    res
+}
+
+// Section
+// the parts of a snapshot that are copied by offset
+#[derive(Clone, Copy)]
+enum Section {
+    WasmModule,
+    MainMemory,
+    StableMemory,
+}
+
+impl Section {
+    const fn kind(self, offset: u64, size: u64) -> SnapshotDataKind {
+        match self {
+            Self::WasmModule => SnapshotDataKind::WasmModule { offset, size },
+            Self::MainMemory => SnapshotDataKind::MainMemory { offset, size },
+            Self::StableMemory => SnapshotDataKind::StableMemory { offset, size },
+        }
+    }
+
+    const fn offset(self, offset: u64) -> SnapshotDataOffset {
+        match self {
+            Self::WasmModule => SnapshotDataOffset::WasmModule { offset },
+            Self::MainMemory => SnapshotDataOffset::MainMemory { offset },
+            Self::StableMemory => SnapshotDataOffset::StableMemory { offset },
+        }
+    }
+}
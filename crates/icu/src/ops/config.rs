@@ -0,0 +1,188 @@
+use crate::{
+    Error,
+    config::{Config, ConfigError, ConfigSource, ConfigView},
+    memory::{
+        CanisterRegistry, CanisterState, ConfigOverlay, ConfigOverlayData,
+        canister::registry::CanisterStatus, config_overlay::ConfigCommand,
+    },
+    ops::{
+        prelude::*,
+        state::{StateBundle, cascade},
+    },
+    state::wasm::WasmRegistry,
+};
+
+///
+/// ConfigHash
+/// one canister's config hash, as seen by root
+///
+/// hash  : the error if the canister couldn't be asked
+/// stale : the hash differs from root's
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize)]
+pub struct ConfigHash {
+    pub pid: Principal,
+    pub canister_type: CanisterType,
+    pub hash: Result<Vec<u8>, String>,
+    pub stale: bool,
+}
+
+///
+/// config_view
+///
+pub fn config_view() -> Result<ConfigView, Error> {
+    Ok(ConfigView {
+        source: Config::source(),
+        profile: Config::profile(),
+        hash: Config::hash(),
+        config: Config::try_get()?,
+    })
+}
+
+///
+/// config_hashes
+/// asks every installed canister in the registry for its config hash, so
+/// canisters built against an old icu.toml stand out
+///
+pub async fn config_hashes() -> Result<Vec<ConfigHash>, Error> {
+    if !CanisterState::is_root() {
+        Err(OpsError::NotRoot)?;
+    }
+
+    let root_hash = Config::hash();
+    let mut hashes = Vec::new();
+
+    for (pid, entry) in CanisterRegistry::export() {
+        if pid == canister_self() || entry.status == CanisterStatus::Created {
+            continue;
+        }
+
+        let hash = config_hash(pid).await.map_err(|e| e.to_string());
+        let stale = hash.as_ref().map_or(true, |hash| *hash != root_hash);
+
+        hashes.push(ConfigHash {
+            pid,
+            canister_type: entry.canister_type,
+            hash,
+            stale,
+        });
+    }
+
+    Ok(hashes)
+}
+
+// config_hash
+async fn config_hash(pid: Principal) -> Result<Vec<u8>, Error> {
+    let res = Call::unbounded_wait(pid, "icu_config_hash")
+        .await
+        .map_err(InterfaceError::from)?;
+
+    Ok(res.candid::<Vec<u8>>().map_err(InterfaceError::from)?)
+}
+
+///
+/// update_config
+/// root builds and validates the new config first, so a bad overlay never
+/// reaches stable memory or the children
+///
+pub async fn update_config(cmd: ConfigCommand) -> Result<(), Error> {
+    if !CanisterState::is_root() {
+        Err(OpsError::NotRoot)?;
+    }
+
+    let current = ConfigOverlay::export();
+    let overlay = match cmd {
+        ConfigCommand::Replace(toml) => ConfigOverlayData {
+            toml: Some(toml),
+            replace: true,
+        },
+        ConfigCommand::Patch(patch) => ConfigOverlayData {
+            toml: Some(Config::merge_patch(current.toml.as_deref(), &patch)?),
+            replace: current.replace,
+        },
+        ConfigCommand::Reset => ConfigOverlayData::default(),
+    };
+
+    let config = Config::build(&overlay)?;
+    if config
+        .endpoints
+        .get("icu_config_update")
+        .is_some_and(|endpoint| endpoint.disabled)
+    {
+        Err(ConfigError::WouldLockOut)?;
+    }
+    config
+        .validate_wasms(&WasmRegistry::types())
+        .map_err(ConfigError::from)?;
+
+    Config::apply(&overlay)?;
+    ConfigOverlay::import(overlay);
+    log!(Log::Ok, "⚙️ config updated");
+
+    cascade(&StateBundle::config_overlay()).await
+}
+
+///
+/// apply_overlay
+/// called with a cascaded overlay.  It's only stored once it applies, a canister
+/// that can't use it keeps its current config and overlay
+///
+pub fn apply_overlay(overlay: ConfigOverlayData) {
+    // during init the bundle arrives before the config is loaded,
+    // apply_stored_overlay picks it up straight after
+    if Config::try_get().is_err() {
+        ConfigOverlay::import(overlay);
+        return;
+    }
+
+    match Config::apply(&overlay) {
+        Ok(()) => ConfigOverlay::import(overlay),
+        Err(e) => log!(Log::Error, "⚙️ config overlay not applied: {e}"),
+    }
+}
+
+// apply_stored_overlay
+// once the baked-in config has loaded
+pub fn apply_stored_overlay() {
+    let overlay = ConfigOverlay::export();
+    if overlay.toml.is_none() || Config::try_get().is_err() {
+        return;
+    }
+
+    if let Err(e) = Config::apply(&overlay) {
+        log!(Log::Error, "⚙️ config overlay not applied: {e}");
+    }
+}
+
+///
+/// check_wasms
+/// root, once the wasms are imported.  A stored overlay that needs a wasm this
+/// build doesn't have is dropped for the baked-in config, only a baked-in config
+/// that's missing one traps (so the upgrade rolls back)
+///
+pub fn check_wasms() {
+    let types = WasmRegistry::types();
+    let Ok(config) = Config::try_get() else {
+        return;
+    };
+    let Err(e) = config.validate_wasms(&types) else {
+        return;
+    };
+
+    if Config::source() == ConfigSource::BakedIn {
+        panic!("config: {e}");
+    }
+
+    log!(
+        Log::Error,
+        "⚙️ config overlay dropped, back to the baked-in config: {e}"
+    );
+    let baked_in = ConfigOverlayData::default();
+    Config::apply(&baked_in).unwrap();
+    ConfigOverlay::import(baked_in);
+
+    if let Err(e) = Config::try_get().unwrap().validate_wasms(&types) {
+        panic!("config: {e}");
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    Error,
+    interface::ic::{start_canister, stop_canister},
+    memory::{
+        AppState, AppStateData, CanisterRegistry, CanisterState, app_state::AppMode,
+        canister::registry::CanisterStatus,
+    },
+    ops::{
+        prelude::*,
+        state::{StateBundle, update_canister},
+    },
+};
+use thiserror::Error as ThisError;
+
+///
+/// LifecycleError
+///
+
+#[derive(Debug, ThisError)]
+pub enum LifecycleError {
+    #[error("the root canister can't be stopped or drained")]
+    RootNotAllowed,
+
+    #[error("canister {0} can't be {1:?} while {2:?}")]
+    InvalidStatus(Principal, CanisterCommand, CanisterStatus),
+}
+
+///
+/// CanisterCommand
+///
+/// Drain : puts the canister into readonly mode
+/// Stop  : drains (if needed) then stops the canister
+/// Start : starts the canister and restores the mode it had before
+///
+
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum CanisterCommand {
+    Drain,
+    Stop,
+    Start,
+}
+
+///
+/// Step
+/// what canister_command does to the canister, in order
+///
+/// Drain   : root pins the canister to Readonly and pushes its app state
+/// Undrain : root removes the pin and pushes its app state
+///
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Step {
+    Drain,
+    Stop,
+    Start,
+    Undrain,
+}
+
+///
+/// canister_command
+/// runs on root, returns the new registry status
+///
+pub async fn canister_command(
+    canister_pid: Principal,
+    cmd: CanisterCommand,
+) -> Result<CanisterStatus, Error> {
+    if !CanisterState::is_root() {
+        Err(OpsError::NotRoot)?;
+    }
+    if canister_pid == canister_self() {
+        Err(OpsError::from(LifecycleError::RootNotAllowed))?;
+    }
+
+    let status = CanisterRegistry::try_get(canister_pid)?.status;
+    let steps = steps(cmd, &status).ok_or_else(|| {
+        OpsError::from(LifecycleError::InvalidStatus(
+            canister_pid,
+            cmd,
+            status.clone(),
+        ))
+    })?;
+
+    let mut previous = match status {
+        CanisterStatus::Draining(mode) | CanisterStatus::Stopped(mode) => mode,
+        _ => AppMode::default(),
+    };
+    for step in steps {
+        match step {
+            Step::Drain => previous = drain(canister_pid).await?,
+            Step::Stop => stop_canister(canister_pid).await?,
+            Step::Start => start_canister(canister_pid).await?,
+            Step::Undrain => undrain(canister_pid).await?,
+        }
+    }
+
+    let new_status = next_status(cmd, previous);
+    CanisterRegistry::set_status(canister_pid, new_status.clone())?;
+
+    log!(
+        Log::Ok,
+        "🚦 canister_command: {canister_pid} {cmd:?} -> {new_status:?}"
+    );
+
+    Ok(new_status)
+}
+
+// steps
+// None if the command isn't allowed from this status
+const fn steps(cmd: CanisterCommand, status: &CanisterStatus) -> Option<&'static [Step]> {
+    let steps: &[Step] = match (cmd, status) {
+        (CanisterCommand::Drain, CanisterStatus::Installed) => &[Step::Drain],
+        (CanisterCommand::Stop, CanisterStatus::Installed) => &[Step::Drain, Step::Stop],
+        (CanisterCommand::Stop, CanisterStatus::Draining(_)) => &[Step::Stop],
+        (CanisterCommand::Start, CanisterStatus::Draining(_)) => &[Step::Undrain],
+        (CanisterCommand::Start, CanisterStatus::Stopped(_)) => &[Step::Start, Step::Undrain],
+        _ => return None,
+    };
+
+    Some(steps)
+}
+
+// next_status
+// previous is the mode the canister had before it was drained
+const fn next_status(cmd: CanisterCommand, previous: AppMode) -> CanisterStatus {
+    match cmd {
+        CanisterCommand::Drain => CanisterStatus::Draining(previous),
+        CanisterCommand::Stop => CanisterStatus::Stopped(previous),
+        CanisterCommand::Start => CanisterStatus::Installed,
+    }
+}
+
+// drain
+// pins the canister to readonly, returning the mode it was in.  The pin lives in
+// root's AppState, so every later cascade carries it too
+async fn drain(canister_pid: Principal) -> Result<AppMode, Error> {
+    let previous = app_state(canister_pid).await?.mode();
+
+    AppState::pin_canister(canister_pid, AppMode::Readonly);
+    if let Err(e) = push_app_state(canister_pid).await {
+        AppState::unpin_canister(canister_pid);
+        return Err(e);
+    }
+
+    Ok(previous)
+}
+
+// undrain
+// the canister goes back to whatever the app and type modes say now
+async fn undrain(canister_pid: Principal) -> Result<(), Error> {
+    AppState::unpin_canister(canister_pid);
+
+    push_app_state(canister_pid).await
+}
+
+// push_app_state
+async fn push_app_state(canister_pid: Principal) -> Result<(), Error> {
+    update_canister(&canister_pid, &StateBundle::app_state()).await
+}
+
+// app_state
+async fn app_state(canister_pid: Principal) -> Result<AppStateData, Error> {
+    let res = Call::unbounded_wait(canister_pid, "icu_app_state")
+        .await
+        .map_err(InterfaceError::from)?;
+
+    Ok(res.candid::<AppStateData>().map_err(InterfaceError::from)?)
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // run
+    // applies a command the way canister_command would, without the calls
+    fn run(cmd: CanisterCommand, status: &CanisterStatus) -> Option<CanisterStatus> {
+        let steps = steps(cmd, status)?;
+        let previous = match status {
+            CanisterStatus::Draining(mode) | CanisterStatus::Stopped(mode) => *mode,
+            _ if steps.contains(&Step::Drain) => AppMode::Enabled,
+            _ => AppMode::default(),
+        };
+
+        Some(next_status(cmd, previous))
+    }
+
+    #[test]
+    fn drain_stop_start() {
+        let drained = run(CanisterCommand::Drain, &CanisterStatus::Installed).unwrap();
+        assert_eq!(drained, CanisterStatus::Draining(AppMode::Enabled));
+
+        let stopped = run(CanisterCommand::Stop, &drained).unwrap();
+        assert_eq!(stopped, CanisterStatus::Stopped(AppMode::Enabled));
+        assert_eq!(
+            steps(CanisterCommand::Stop, &drained),
+            Some(&[Step::Stop][..])
+        );
+
+        let started = run(CanisterCommand::Start, &stopped).unwrap();
+        assert_eq!(started, CanisterStatus::Installed);
+        assert_eq!(
+            steps(CanisterCommand::Start, &stopped),
+            Some(&[Step::Start, Step::Undrain][..])
+        );
+
+        // stopping an installed canister drains it first
+        assert_eq!(
+            steps(CanisterCommand::Stop, &CanisterStatus::Installed),
+            Some(&[Step::Drain, Step::Stop][..])
+        );
+        assert_eq!(
+            run(
+                CanisterCommand::Start,
+                &CanisterStatus::Draining(AppMode::Readonly)
+            ),
+            Some(CanisterStatus::Installed)
+        );
+    }
+
+    #[test]
+    fn invalid_transitions() {
+        let draining = CanisterStatus::Draining(AppMode::Enabled);
+        let stopped = CanisterStatus::Stopped(AppMode::Enabled);
+
+        for (cmd, status) in [
+            (CanisterCommand::Start, &CanisterStatus::Installed),
+            (CanisterCommand::Drain, &draining),
+            (CanisterCommand::Drain, &stopped),
+            (CanisterCommand::Stop, &stopped),
+            (CanisterCommand::Drain, &CanisterStatus::Created),
+            (CanisterCommand::Stop, &CanisterStatus::Created),
+            (CanisterCommand::Start, &CanisterStatus::Created),
+        ] {
+            assert!(steps(cmd, status).is_none(), "{cmd:?} from {status:?}");
+        }
+    }
+}
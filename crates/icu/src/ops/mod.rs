@@ -1,7 +1,11 @@
 pub mod canister;
+pub mod clone;
+pub mod config;
+pub mod lifecycle;
 pub mod pool;
 pub mod request;
 pub mod response;
+pub mod role;
 pub mod root;
 pub mod state;
 
@@ -33,9 +37,21 @@ pub enum OpsError {
     #[error("this function can only be called from the root canister")]
     NotRoot,
 
+    #[error(transparent)]
+    CanisterError(#[from] canister::CanisterError),
+
+    #[error(transparent)]
+    CloneError(#[from] clone::CloneError),
+
     #[error(transparent)]
     InterfaceError(#[from] InterfaceError),
 
+    #[error(transparent)]
+    LifecycleError(#[from] lifecycle::LifecycleError),
+
+    #[error(transparent)]
+    PoolError(#[from] pool::PoolError),
+
     #[error(transparent)]
     RequestError(#[from] request::RequestError),
 }
@@ -1,11 +1,39 @@
 use crate::{
     Error, Log,
-    interface::ic::{get_cycles, uninstall_code},
-    memory::{CanisterPool, CanisterRegistry, CanisterState},
-    ops::canister::create_canister,
+    cdk::{api::canister_liquid_cycle_balance, call::Call},
+    config::Config,
+    interface::ic::{
+        canister_status, delete_canister, deposit_cycles, get_cycles, stop_canister, uninstall_code,
+    },
+    memory::{CanisterPool, CanisterRegistry, CanisterState, canister::CanisterEntry},
+    ops::canister::{create_canister, get_controllers, install_canister},
     ops::prelude::*,
-    types::{Cycles, TC},
+    state::{
+        pool::{PoolAction, PoolReport},
+        wasm::WasmRegistry,
+    },
+    types::{BC, Cycles, TC},
 };
+use thiserror::Error as ThisError;
+
+///
+/// PoolError
+///
+
+#[derive(Debug, ThisError)]
+pub enum PoolError {
+    #[error("pool canister {0} has controllers that don't match")]
+    ControllerMismatch(Principal),
+
+    #[error("pool canister {0} already has a module installed")]
+    ModuleInstalled(Principal),
+
+    #[error("pool canister {0} is not quarantined")]
+    NotQuarantined(Principal),
+
+    #[error("there's no wasm to install on pool canister {0} to reclaim its cycles")]
+    NoWasm(Principal),
+}
 
 ///
 /// Constants
@@ -13,6 +41,9 @@ use crate::{
 
 const POOL_CANISTER_CYCLES: Cycles = Cycles::new(5 * TC);
 
+// cycles kept back by a canister returning its balance, to pay for the call itself
+const CYCLES_RETURN_MARGIN: u128 = 10 * BC;
+
 ///
 /// create_pool_canister
 /// creates an empty canister and registers it with the CanisterPool
@@ -30,18 +61,27 @@ pub async fn create_pool_canister() -> Result<Principal, Error> {
     );
 
     CanisterPool::register(canister_pid, POOL_CANISTER_CYCLES);
+    PoolReport::record(canister_pid, PoolAction::Created(POOL_CANISTER_CYCLES));
 
     Ok(canister_pid)
 }
 
 ///
 /// move_canister_to_pool
+/// if the pool is already at its maximum size the canister is reclaimed instead
 ///
 pub async fn move_canister_to_pool(canister_pid: Principal) -> Result<(), Error> {
     if !CanisterState::is_root() {
         Err(OpsError::NotRoot)?;
     }
 
+    let config = Config::try_get()?;
+    if let Some(max) = config.pool.maximum_size
+        && CanisterPool::ready_len() >= u64::from(max)
+    {
+        return reclaim_canister(canister_pid).await;
+    }
+
     // uninstall code
     uninstall_code(canister_pid).await?;
 
@@ -55,6 +95,7 @@ pub async fn move_canister_to_pool(canister_pid: Principal) -> Result<(), Error>
     // register to Pool
     let cycles = get_cycles(canister_pid).await?;
     CanisterPool::register(canister_pid, cycles);
+    PoolReport::record(canister_pid, PoolAction::MovedIn(cycles));
 
     log!(
         Log::Ok,
@@ -63,3 +104,240 @@ pub async fn move_canister_to_pool(canister_pid: Principal) -> Result<(), Error>
 
     Ok(())
 }
+
+///
+/// reconcile_pool
+/// refreshes the cycle balance of every pool canister, tops up the ones below
+/// the configured threshold and reclaims any canisters above the maximum size
+///
+pub async fn reconcile_pool() -> Result<(), Error> {
+    if !CanisterState::is_root() {
+        Err(OpsError::NotRoot)?;
+    }
+
+    let config = Config::try_get()?;
+
+    // newest first, the oldest ones get reused first anyway
+    let mut ready = CanisterPool::export();
+    ready.retain(|(_, entry)| entry.is_ready());
+    ready.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.created_at));
+
+    // trim pass
+    if let Some(max) = config.pool.maximum_size {
+        let surplus = ready.len().saturating_sub(usize::from(max));
+
+        if surplus > 0 {
+            log!(
+                Log::Ok,
+                "💧 canister pool over maximum ({max}), reclaiming {surplus}"
+            );
+
+            for (pid, _) in ready.drain(..surplus) {
+                if let Err(e) = reclaim_pool_canister(pid).await {
+                    PoolReport::record(pid, PoolAction::Failed(e.to_string()));
+                }
+            }
+        }
+    }
+
+    // refresh + topup pass
+    // quarantined canisters are left alone, we don't fund something we don't trust
+    for (pid, _) in ready {
+        let mut cycles = match get_cycles(pid).await {
+            Ok(cycles) => cycles,
+            Err(e) => {
+                PoolReport::record(pid, PoolAction::Failed(e.to_string()));
+                continue;
+            }
+        };
+
+        if let Some(topup) = &config.pool.topup
+            && cycles < topup.threshold
+        {
+            match deposit_cycles(pid, topup.amount).await {
+                Ok(()) => {
+                    cycles += topup.amount;
+                    PoolReport::record(pid, PoolAction::ToppedUp(topup.amount));
+
+                    log!(Log::Ok, "💧 pool topup: {pid} by {}", topup.amount);
+                }
+                Err(e) => PoolReport::record(pid, PoolAction::Failed(e.to_string())),
+            }
+        }
+
+        // it may have been allocated while we were awaiting
+        let _ = CanisterPool::update_cycles(&pid, cycles);
+    }
+
+    Ok(())
+}
+
+///
+/// verify_pool_canister
+/// checks a pool canister is still safe to reuse: no code installed and the
+/// controllers are exactly what we'd set, then tops it up to min_cycles
+///
+/// the outer error means we couldn't check (ie. a failed call), the inner
+/// PoolError means the canister itself isn't safe and should be quarantined
+///
+pub async fn verify_pool_canister(
+    canister_pid: Principal,
+    min_cycles: Cycles,
+) -> Result<Result<Cycles, PoolError>, Error> {
+    let status = canister_status(canister_pid).await?;
+
+    if status.module_hash.is_some() {
+        return Ok(Err(PoolError::ModuleInstalled(canister_pid)));
+    }
+
+    let mut expected = get_controllers()?;
+    let mut actual = status.settings.controllers;
+    expected.sort();
+    expected.dedup();
+    actual.sort();
+    actual.dedup();
+
+    if expected != actual {
+        return Ok(Err(PoolError::ControllerMismatch(canister_pid)));
+    }
+
+    let mut cycles: Cycles = status
+        .cycles
+        .try_into()
+        .map_err(|_| InterfaceError::CyclesOverflow)?;
+
+    if cycles < min_cycles {
+        let shortfall = min_cycles - cycles;
+        deposit_cycles(canister_pid, shortfall).await?;
+        PoolReport::record(canister_pid, PoolAction::ToppedUp(shortfall));
+
+        cycles = min_cycles;
+    }
+
+    Ok(Ok(cycles))
+}
+
+///
+/// release_pool_canister
+/// drops a quarantined canister from the pool, root leaves the canister itself
+/// alone. Once it's fixed it can come back in through icu_adopt_canister
+///
+pub fn release_pool_canister(canister_pid: Principal) -> Result<(), Error> {
+    if !CanisterState::is_root() {
+        Err(OpsError::NotRoot)?;
+    }
+
+    let entry = CanisterPool::release(&canister_pid)
+        .ok_or_else(|| OpsError::from(PoolError::NotQuarantined(canister_pid)))?;
+
+    PoolReport::record(canister_pid, PoolAction::Released);
+    log!(
+        Log::Ok,
+        "💧 release_pool_canister: {canister_pid} (was {})",
+        entry.quarantined.unwrap_or_default()
+    );
+
+    Ok(())
+}
+
+///
+/// reclaim_canister
+/// asks an installed canister to return its cycles to root, then deletes it
+///
+async fn reclaim_canister(canister_pid: Principal) -> Result<(), Error> {
+    // carry on if this fails, the canister is being removed either way
+    let returned = match request_cycles_return(canister_pid).await {
+        Ok(cycles) => cycles,
+        Err(e) => {
+            log!(Log::Warn, "💧 {canister_pid} could not return cycles: {e}");
+            Cycles::default()
+        }
+    };
+
+    stop_and_delete(canister_pid).await?;
+    let _ = CanisterRegistry::remove(&canister_pid);
+
+    PoolReport::record(canister_pid, PoolAction::Reclaimed(returned));
+    log!(
+        Log::Ok,
+        "💧 reclaim_canister: {canister_pid} (pool full, {returned} returned)",
+    );
+
+    Ok(())
+}
+
+// reclaim_pool_canister
+// pool canisters have no code to send their cycles back with, so the smallest
+// registered wasm is installed first (costs far less than the balance), then
+// it's reclaimed like any other canister.  If that fails it goes back into the
+// pool, where it'll be quarantined if the install stuck
+async fn reclaim_pool_canister(canister_pid: Principal) -> Result<(), Error> {
+    let Some(entry) = CanisterPool::remove(&canister_pid) else {
+        return Ok(());
+    };
+
+    let returned = async {
+        let ty = WasmRegistry::smallest()
+            .ok_or_else(|| OpsError::from(PoolError::NoWasm(canister_pid)))?;
+        install_canister(canister_pid, &ty, &[CanisterEntry::this()?], None).await?;
+
+        match request_cycles_return(canister_pid).await {
+            Ok(cycles) => Ok(cycles),
+            Err(e) => {
+                let _ = uninstall_code(canister_pid).await;
+                Err(e)
+            }
+        }
+    }
+    .await;
+
+    let returned = match returned {
+        Ok(cycles) => cycles,
+        Err(e) => {
+            CanisterPool::restore(canister_pid, entry);
+            return Err(e);
+        }
+    };
+
+    stop_and_delete(canister_pid).await?;
+
+    PoolReport::record(canister_pid, PoolAction::Reclaimed(returned));
+    log!(
+        Log::Ok,
+        "💧 reclaim_pool_canister: {canister_pid} (pool over maximum, {returned} returned)",
+    );
+
+    Ok(())
+}
+
+// request_cycles_return
+async fn request_cycles_return(canister_pid: Principal) -> Result<Cycles, Error> {
+    let res = Call::unbounded_wait(canister_pid, "icu_cycles_return")
+        .await
+        .map_err(InterfaceError::from)?;
+
+    res.candid::<Result<Cycles, Error>>()
+        .map_err(InterfaceError::from)?
+}
+
+// stop_and_delete
+async fn stop_and_delete(canister_pid: Principal) -> Result<(), Error> {
+    stop_canister(canister_pid).await?;
+    delete_canister(canister_pid).await?;
+
+    Ok(())
+}
+
+///
+/// return_cycles
+/// called on the canister that root is about to reclaim, sends the
+/// liquid balance (minus a margin for the call) back to root
+///
+pub async fn return_cycles() -> Result<Cycles, Error> {
+    let root_pid = CanisterState::get_root_pid();
+    let cycles = Cycles::new(canister_liquid_cycle_balance().saturating_sub(CYCLES_RETURN_MARGIN));
+
+    deposit_cycles(root_pid, cycles).await?;
+
+    Ok(cycles)
+}
@@ -3,8 +3,12 @@ use crate::{
     cdk::call::Call,
     memory::{CanisterChildren, CanisterState, canister::CanisterEntry},
     ops::{
+        lifecycle::CanisterCommand,
         prelude::*,
-        response::{CreateCanisterResponse, CyclesResponse, Response, UpgradeCanisterResponse},
+        response::{
+            CreateCanisterResponse, CyclesResponse, LifecycleResponse, Response,
+            UpgradeCanisterResponse,
+        },
     },
 };
 use candid::encode_one;
@@ -19,6 +23,9 @@ pub enum RequestError {
     #[error("this request is not allowed to be called on root")]
     RootNotAllowed,
 
+    #[error("caller '{0}' is not the parent of canister '{1}'")]
+    NotParent(Principal, Principal),
+
     #[error("invalid response type")]
     InvalidResponseType,
 }
@@ -32,6 +39,7 @@ pub enum Request {
     CreateCanister(CreateCanisterRequest),
     UpgradeCanister(UpgradeCanisterRequest),
     Cycles(CyclesRequest),
+    Lifecycle(LifecycleRequest),
 }
 
 ///
@@ -65,6 +73,17 @@ pub struct CyclesRequest {
     pub cycles: Cycles,
 }
 
+///
+/// LifecycleRequest
+/// drains, stops or starts one of the caller's own children
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct LifecycleRequest {
+    pub canister_pid: Principal,
+    pub command: CanisterCommand,
+}
+
 ///
 /// REQUEST
 ///
@@ -152,3 +171,22 @@ pub async fn cycles_request(cycles: Cycles) -> Result<CyclesResponse, Error> {
         _ => Err(OpsError::RequestError(RequestError::InvalidResponseType))?,
     }
 }
+
+// lifecycle_request
+pub async fn lifecycle_request(
+    canister_pid: Principal,
+    command: CanisterCommand,
+) -> Result<LifecycleResponse, Error> {
+    // check this is a valid child
+    CanisterChildren::try_get(&canister_pid)?;
+
+    let q = Request::Lifecycle(LifecycleRequest {
+        canister_pid,
+        command,
+    });
+
+    match request(q).await? {
+        Response::Lifecycle(res) => Ok(res),
+        _ => Err(OpsError::RequestError(RequestError::InvalidResponseType))?,
+    }
+}
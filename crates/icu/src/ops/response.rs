@@ -4,10 +4,15 @@ use crate::{
         ic::{deposit_cycles, upgrade_canister},
         prelude::*,
     },
-    memory::CanisterState,
+    memory::{CanisterRegistry, CanisterState, canister::registry::CanisterStatus},
     ops::{
+        OpsError,
         canister::create_and_install_canister,
-        request::{CreateCanisterRequest, CyclesRequest, Request, UpgradeCanisterRequest},
+        lifecycle::canister_command,
+        request::{
+            CreateCanisterRequest, CyclesRequest, LifecycleRequest, Request, RequestError,
+            UpgradeCanisterRequest,
+        },
     },
     state::wasm::WasmRegistry,
 };
@@ -22,6 +27,7 @@ pub enum Response {
     CreateCanister(CreateCanisterResponse),
     UpgradeCanister(UpgradeCanisterResponse),
     Cycles(CyclesResponse),
+    Lifecycle(LifecycleResponse),
 }
 
 ///
@@ -49,6 +55,15 @@ pub struct CyclesResponse {
     pub cycles_transferred: Cycles,
 }
 
+///
+/// LifecycleResponse
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct LifecycleResponse {
+    pub status: CanisterStatus,
+}
+
 // response
 pub async fn response(req: Request) -> Result<Response, Error> {
     assert!(CanisterState::is_root(), "only root can run this code");
@@ -57,6 +72,7 @@ pub async fn response(req: Request) -> Result<Response, Error> {
         Request::CreateCanister(req) => create_canister_response(&req).await,
         Request::UpgradeCanister(req) => upgrade_canister_response(&req).await,
         Request::Cycles(req) => cycles_response(&req).await,
+        Request::Lifecycle(req) => lifecycle_response(&req).await,
     }
 }
 
@@ -87,3 +103,21 @@ async fn cycles_response(req: &CyclesRequest) -> Result<Response, Error> {
 
     Ok(Response::Cycles(CyclesResponse { cycles_transferred }))
 }
+
+// lifecycle_response
+// the caller has to be the registered parent of the target
+async fn lifecycle_response(req: &LifecycleRequest) -> Result<Response, Error> {
+    let caller = msg_caller();
+    let entry = CanisterRegistry::try_get(req.canister_pid)?;
+
+    if entry.parent_pid != Some(caller) {
+        Err(OpsError::RequestError(RequestError::NotParent(
+            caller,
+            req.canister_pid,
+        )))?;
+    }
+
+    let status = canister_command(req.canister_pid, req.command).await?;
+
+    Ok(Response::Lifecycle(LifecycleResponse { status }))
+}
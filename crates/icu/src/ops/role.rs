@@ -0,0 +1,40 @@
+use crate::{
+    Error,
+    memory::RoleRegistry,
+    ops::{
+        prelude::*,
+        state::{StateBundle, cascade},
+    },
+};
+
+///
+/// grant_role
+/// cascade pushes the whole role registry down to every canister below this one,
+/// replacing whatever roles they had
+///
+pub async fn grant_role(role: &str, pid: Principal, cascade_roles: bool) -> Result<(), Error> {
+    RoleRegistry::grant(role, pid)?;
+
+    log!(Log::Ok, "🔑 grant_role: '{role}' -> {pid}");
+
+    if cascade_roles {
+        cascade(&StateBundle::role_registry()).await?;
+    }
+
+    Ok(())
+}
+
+///
+/// revoke_role
+///
+pub async fn revoke_role(role: &str, pid: Principal, cascade_roles: bool) -> Result<(), Error> {
+    RoleRegistry::revoke(role, pid)?;
+
+    log!(Log::Ok, "🔑 revoke_role: '{role}' from {pid}");
+
+    if cascade_roles {
+        cascade(&StateBundle::role_registry()).await?;
+    }
+
+    Ok(())
+}
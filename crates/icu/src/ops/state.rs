@@ -1,7 +1,11 @@
 use crate::{
     Error,
     interface::prelude::*,
-    memory::{AppState, AppStateData, CanisterChildren, CanisterDirectory, CanisterDirectoryView},
+    memory::{
+        AppState, AppStateData, CanisterChildren, CanisterDirectory, CanisterDirectoryView,
+        ConfigOverlay, ConfigOverlayData, FeatureFlags, FeatureFlagsData, RoleRegistry,
+        RoleRegistryView, Whitelist, WhitelistData,
+    },
 };
 
 ///
@@ -12,6 +16,10 @@ use crate::{
 pub struct StateBundle {
     app_state: Option<AppStateData>,
     canister_directory: Option<CanisterDirectoryView>,
+    role_registry: Option<RoleRegistryView>,
+    whitelist: Option<WhitelistData>,
+    feature_flags: Option<FeatureFlagsData>,
+    config_overlay: Option<ConfigOverlayData>,
 }
 
 impl StateBundle {
@@ -20,6 +28,10 @@ impl StateBundle {
         Self {
             app_state: Some(AppState::export()),
             canister_directory: Some(CanisterDirectory::export()),
+            role_registry: Some(RoleRegistry::export()),
+            whitelist: Some(Whitelist::export()),
+            feature_flags: Some(FeatureFlags::export()),
+            config_overlay: Some(ConfigOverlay::export()),
         }
     }
 
@@ -31,6 +43,16 @@ impl StateBundle {
         }
     }
 
+    // with_app_state
+    // for pushing a specific app state to a single canister
+    #[must_use]
+    pub fn with_app_state(data: AppStateData) -> Self {
+        Self {
+            app_state: Some(data),
+            ..Default::default()
+        }
+    }
+
     #[must_use]
     pub fn canister_directory() -> Self {
         Self {
@@ -39,9 +61,46 @@ impl StateBundle {
         }
     }
 
+    #[must_use]
+    pub fn role_registry() -> Self {
+        Self {
+            role_registry: Some(RoleRegistry::export()),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn whitelist() -> Self {
+        Self {
+            whitelist: Some(Whitelist::export()),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn feature_flags() -> Self {
+        Self {
+            feature_flags: Some(FeatureFlags::export()),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn config_overlay() -> Self {
+        Self {
+            config_overlay: Some(ConfigOverlay::export()),
+            ..Default::default()
+        }
+    }
+
     #[must_use]
     pub const fn is_empty(&self) -> bool {
-        self.app_state.is_none() && self.canister_directory.is_none()
+        self.app_state.is_none()
+            && self.canister_directory.is_none()
+            && self.role_registry.is_none()
+            && self.whitelist.is_none()
+            && self.feature_flags.is_none()
+            && self.config_overlay.is_none()
     }
 
     fn debug(&self) -> String {
@@ -53,6 +112,18 @@ impl StateBundle {
         if self.canister_directory.is_some() {
             debug_str.push('s');
         }
+        if self.role_registry.is_some() {
+            debug_str.push('r');
+        }
+        if self.whitelist.is_some() {
+            debug_str.push('w');
+        }
+        if self.feature_flags.is_some() {
+            debug_str.push('f');
+        }
+        if self.config_overlay.is_some() {
+            debug_str.push('c');
+        }
 
         debug_str
     }
@@ -61,11 +132,23 @@ impl StateBundle {
 // save_state
 pub fn save_state(bundle: &StateBundle) {
     if let Some(data) = &bundle.app_state {
-        AppState::import(*data);
+        AppState::import(data.clone());
     }
     if let Some(data) = &bundle.canister_directory {
         CanisterDirectory::import(data.clone());
     }
+    if let Some(data) = &bundle.role_registry {
+        RoleRegistry::import(data.clone());
+    }
+    if let Some(data) = &bundle.whitelist {
+        Whitelist::import(data.clone());
+    }
+    if let Some(data) = &bundle.feature_flags {
+        FeatureFlags::import(data.clone());
+    }
+    if let Some(data) = &bundle.config_overlay {
+        crate::ops::config::apply_overlay(data.clone());
+    }
 }
 
 // cascade
@@ -38,17 +38,33 @@ pub enum DelegationRegistryError {
 
     #[error("session length cannot exceed {0} seconds")]
     SessionTooLong(u64),
+
+    #[error("principal '{0}' is already a session key for another wallet")]
+    AlreadyDelegated(Principal),
+
+    #[error("session '{0}' hasn't been confirmed by the session key")]
+    NotConfirmed(Principal),
+
+    #[error("session '{0}' was registered by a different wallet")]
+    WalletMismatch(Principal),
+
+    #[error("'{0}' can't be a session key")]
+    InvalidSessionKey(Principal),
 }
 
 ///
 /// DelegationSession
 ///
+/// confirmed : the session key has agreed to act for the wallet, until then
+///             the session doesn't resolve
+///
 
 #[derive(CandidType, Clone, Debug, Deserialize)]
 pub struct DelegationSession {
     wallet_pid: Principal,
     expires_at: u64,
     requesting_canisters: Vec<Principal>,
+    confirmed: bool,
 }
 
 impl DelegationSession {
@@ -58,6 +74,7 @@ impl DelegationSession {
             wallet_pid,
             expires_at,
             requesting_canisters: Vec::new(),
+            confirmed: false,
         }
     }
 
@@ -129,10 +146,9 @@ impl DelegationRegistry {
         Self::with(HashMap::is_empty)
     }
 
-    /// Returns info about a specific session, including expiration status.
+    /// Returns info about a specific confirmed session, including expiration status.
     pub fn get(session_pid: Principal) -> Result<DelegationSessionView, Error> {
-        let session = Self::with(|map| map.get(&session_pid).cloned())
-            .ok_or_else(|| StateError::from(DelegationRegistryError::NotFound(session_pid)))?;
+        let session = Self::try_get_confirmed(session_pid)?;
 
         Ok((session_pid, &session).into())
     }
@@ -145,6 +161,11 @@ impl DelegationRegistry {
             let session = map
                 .get_mut(&session_pid)
                 .ok_or_else(|| StateError::from(DelegationRegistryError::NotFound(session_pid)))?;
+            if !session.confirmed {
+                Err(StateError::from(DelegationRegistryError::NotConfirmed(
+                    session_pid,
+                )))?;
+            }
 
             if !session.requesting_canisters.contains(&caller) {
                 session.requesting_canisters.push(caller);
@@ -156,9 +177,8 @@ impl DelegationRegistry {
 
     /// Resolves the wallet (grantor) associated with a valid, non-expired session.
     pub fn resolve_wallet(caller: Principal) -> Result<Principal, Error> {
-        // Check if session exists
-        let session = Self::with(|map| map.get(&caller).cloned())
-            .ok_or_else(|| StateError::from(DelegationRegistryError::NotFound(caller)))?;
+        // Check if a confirmed session exists
+        let session = Self::try_get_confirmed(caller)?;
 
         // Check it has expired
         if session.is_expired() {
@@ -195,9 +215,27 @@ impl DelegationRegistry {
 
     /// Registers a new session for a wallet with a limited duration.
     /// Removes any previous session associated with the same wallet.
-    /// This call is expected to come from the front end
+    /// This call is expected to come from the front end, and the session
+    /// doesn't resolve until the session key calls confirm_session.
     pub fn register_session(wallet_pid: Principal, args: RegisterSessionArgs) -> Result<(), Error> {
         let duration = Duration::from_secs(args.duration_secs);
+        let session_pid = args.session_pid;
+
+        if session_pid == wallet_pid || session_pid == Principal::anonymous() {
+            Err(StateError::from(
+                DelegationRegistryError::InvalidSessionKey(session_pid),
+            ))?;
+        }
+
+        // a live session can't be taken over by another wallet
+        if Self::with(|map| {
+            map.get(&session_pid)
+                .is_some_and(|s| s.wallet_pid != wallet_pid && !s.is_expired())
+        }) {
+            Err(StateError::from(DelegationRegistryError::AlreadyDelegated(
+                session_pid,
+            )))?;
+        }
 
         // Validate expiration time
         if duration < MIN_EXPIRATION {
@@ -218,12 +256,8 @@ impl DelegationRegistry {
 
             // Insert the new session
             map.insert(
-                args.session_pid,
-                DelegationSession {
-                    wallet_pid,
-                    expires_at: now_secs() + args.duration_secs,
-                    requesting_canisters: Vec::new(),
-                },
+                session_pid,
+                DelegationSession::new(wallet_pid, now_secs() + args.duration_secs),
             );
         });
 
@@ -233,6 +267,45 @@ impl DelegationRegistry {
         Ok(())
     }
 
+    /// Confirms a registered session, called by the session key itself so
+    /// nobody can register someone else's principal as their session.
+    pub fn confirm_session(session_pid: Principal, wallet_pid: Principal) -> Result<(), Error> {
+        Self::with_mut(|map| {
+            let session = map
+                .get_mut(&session_pid)
+                .ok_or_else(|| StateError::from(DelegationRegistryError::NotFound(session_pid)))?;
+
+            if session.wallet_pid != wallet_pid {
+                Err(StateError::from(DelegationRegistryError::WalletMismatch(
+                    session_pid,
+                )))?;
+            }
+            if session.is_expired() {
+                Err(StateError::from(DelegationRegistryError::SessionExpired(
+                    session.expires_at,
+                    now_secs(),
+                )))?;
+            }
+            session.confirmed = true;
+
+            Ok(())
+        })
+    }
+
+    // try_get_confirmed
+    fn try_get_confirmed(session_pid: Principal) -> Result<DelegationSession, Error> {
+        let session = Self::with(|map| map.get(&session_pid).cloned())
+            .ok_or_else(|| StateError::from(DelegationRegistryError::NotFound(session_pid)))?;
+
+        if !session.confirmed {
+            Err(StateError::from(DelegationRegistryError::NotConfirmed(
+                session_pid,
+            )))?;
+        }
+
+        Ok(session)
+    }
+
     ///
     /// Revokes a session or all sessions granted by a wallet principal.
     ///
@@ -325,6 +398,11 @@ mod tests {
         )
         .unwrap();
 
+        // nothing resolves until the session key confirms
+        assert!(DelegationRegistry::get(session).is_err());
+        assert!(DelegationRegistry::resolve_wallet(session).is_err());
+        DelegationRegistry::confirm_session(session, wallet).unwrap();
+
         let view = DelegationRegistry::get(session).unwrap();
         assert_eq!(view.wallet_pid, wallet);
         assert_eq!(view.session_pid, session);
@@ -415,10 +493,53 @@ mod tests {
         let session = dummy_pid(21);
 
         DELEGATION_REGISTRY.with_borrow_mut(|map| {
-            map.insert(session, DelegationSession::new(wallet, now_secs() - 1))
+            let mut session_data = DelegationSession::new(wallet, now_secs() - 1);
+            session_data.confirmed = true;
+            map.insert(session, session_data)
         });
 
         let view = DelegationRegistry::get(session).unwrap();
         assert!(view.is_expired);
     }
+
+    #[test]
+    fn third_party_cant_claim_a_principal() {
+        reset_state();
+        let (wallet, victim, attacker) = (dummy_pid(30), dummy_pid(31), dummy_pid(32));
+        let args = |session_pid| RegisterSessionArgs {
+            session_pid,
+            duration_secs: 300,
+        };
+
+        // registering the victim is allowed, but it's only pending
+        DelegationRegistry::register_session(attacker, args(victim)).unwrap();
+        assert!(DelegationRegistry::resolve_wallet(victim).is_err());
+
+        // only the victim can confirm, and only for the wallet that registered it
+        assert!(DelegationRegistry::confirm_session(attacker, attacker).is_err());
+        assert!(DelegationRegistry::confirm_session(victim, wallet).is_err());
+        assert!(DelegationRegistry::resolve_wallet(victim).is_err());
+
+        // a live session can't be overwritten by another wallet either
+        let session = dummy_pid(33);
+        DelegationRegistry::register_session(wallet, args(session)).unwrap();
+        DelegationRegistry::confirm_session(session, wallet).unwrap();
+
+        assert!(DelegationRegistry::register_session(attacker, args(session)).is_err());
+        assert_eq!(DelegationRegistry::resolve_wallet(session).unwrap(), wallet);
+    }
+
+    #[test]
+    fn invalid_session_keys_are_rejected() {
+        reset_state();
+        let wallet = dummy_pid(40);
+
+        for session_pid in [wallet, Principal::anonymous()] {
+            let args = RegisterSessionArgs {
+                session_pid,
+                duration_secs: 300,
+            };
+            assert!(DelegationRegistry::register_session(wallet, args).is_err());
+        }
+    }
 }
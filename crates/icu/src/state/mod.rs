@@ -1,5 +1,7 @@
 pub mod delegation;
 pub mod icrc;
+pub mod pool;
+pub mod rate_limit;
 pub mod wasm;
 
 use crate::{
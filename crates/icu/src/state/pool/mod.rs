@@ -0,0 +1,3 @@
+mod pool_report;
+
+pub use pool_report::*;
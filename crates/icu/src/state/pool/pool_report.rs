@@ -0,0 +1,133 @@
+use crate::{types::Cycles, utils::time::now_secs};
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+use std::{cell::RefCell, collections::VecDeque};
+
+///
+/// Constants
+///
+
+const MAX_ENTRIES: usize = 500;
+
+//
+// POOL_REPORT
+// (root-only, heap)
+//
+
+thread_local! {
+    static POOL_REPORT: RefCell<VecDeque<PoolReportEntry>> = const { RefCell::new(VecDeque::new()) };
+}
+
+///
+/// PoolAction
+/// everything root does to a pool canister
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum PoolAction {
+    Created(Cycles),
+    MovedIn(Cycles),
+    ToppedUp(Cycles),
+    Reclaimed(Cycles),
+    Quarantined(String),
+    Released,
+    Failed(String),
+}
+
+///
+/// PoolReportEntry
+///
+
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct PoolReportEntry {
+    pub timestamp: u64,
+    pub canister_pid: Principal,
+    pub action: PoolAction,
+}
+
+///
+/// PoolReport
+/// bounded log of pool actions, oldest entries are dropped first
+///
+
+pub type PoolReportView = Vec<PoolReportEntry>;
+
+pub struct PoolReport {}
+
+impl PoolReport {
+    pub fn record(canister_pid: Principal, action: PoolAction) {
+        let entry = PoolReportEntry {
+            timestamp: now_secs(),
+            canister_pid,
+            action,
+        };
+
+        POOL_REPORT.with_borrow_mut(|report| {
+            if report.len() >= MAX_ENTRIES {
+                report.pop_front();
+            }
+            report.push_back(entry);
+        });
+    }
+
+    #[must_use]
+    pub fn len() -> usize {
+        POOL_REPORT.with_borrow(VecDeque::len)
+    }
+
+    #[must_use]
+    pub fn is_empty() -> bool {
+        POOL_REPORT.with_borrow(VecDeque::is_empty)
+    }
+
+    pub fn clear() {
+        POOL_REPORT.with_borrow_mut(VecDeque::clear);
+    }
+
+    #[must_use]
+    pub fn export() -> PoolReportView {
+        POOL_REPORT.with_borrow(|report| report.iter().cloned().collect())
+    }
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dummy_pid(n: u8) -> Principal {
+        Principal::from_slice(&[n; 29])
+    }
+
+    #[test]
+    fn record_and_export_in_order() {
+        PoolReport::clear();
+
+        PoolReport::record(dummy_pid(1), PoolAction::Created(Cycles::new(5)));
+        PoolReport::record(dummy_pid(2), PoolAction::ToppedUp(Cycles::new(7)));
+
+        let view = PoolReport::export();
+        assert_eq!(view.len(), 2);
+        assert_eq!(view[0].canister_pid, dummy_pid(1));
+        assert_eq!(view[1].action, PoolAction::ToppedUp(Cycles::new(7)));
+    }
+
+    #[test]
+    fn report_is_bounded() {
+        PoolReport::clear();
+
+        for i in 0..MAX_ENTRIES + 10 {
+            let n = u8::try_from(i % 200).unwrap();
+            PoolReport::record(dummy_pid(n), PoolAction::ToppedUp(Cycles::new(0)));
+        }
+
+        assert_eq!(PoolReport::len(), MAX_ENTRIES);
+
+        // the first 10 entries were dropped
+        let view = PoolReport::export();
+        assert_eq!(view[0].canister_pid, dummy_pid(10));
+    }
+}
@@ -0,0 +1,3 @@
+mod rate_limiter;
+
+pub use rate_limiter::*;
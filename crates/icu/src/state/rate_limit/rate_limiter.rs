@@ -0,0 +1,252 @@
+use crate::{config::RateLimit, utils::time::now_millis};
+use candid::Principal;
+use std::{cell::RefCell, collections::HashMap};
+
+///
+/// Constants
+///
+
+const MAX_BUCKETS: usize = 10_000;
+const EVICT_INTERVAL_MILLIS: u64 = 60 * 1_000; // 1 minute
+
+//
+// RATE_LIMITER
+// (heap, resets on upgrade)
+//
+
+thread_local! {
+    static RATE_LIMITER: RefCell<RateLimiterCore> = RefCell::new(RateLimiterCore::default());
+}
+
+///
+/// RateLimitKey
+/// method is None when the bucket is shared between methods
+///
+
+pub type RateLimitKey = (Principal, Option<String>);
+
+///
+/// Bucket
+///
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: u64,
+}
+
+///
+/// RateLimiter
+///
+
+pub struct RateLimiter {}
+
+impl RateLimiter {
+    // check
+    // takes a token from the caller's bucket, false if it's empty
+    #[must_use]
+    pub fn check(key: RateLimitKey, limit: RateLimit) -> bool {
+        RATE_LIMITER.with_borrow_mut(|core| core.check(key, limit, now_millis()))
+    }
+
+    // peek
+    // true if check would pass, without taking the token
+    #[must_use]
+    pub fn peek(key: RateLimitKey, limit: RateLimit) -> bool {
+        RATE_LIMITER.with_borrow(|core| core.peek(&key, limit, now_millis()))
+    }
+
+    #[must_use]
+    pub fn len() -> usize {
+        RATE_LIMITER.with_borrow(RateLimiterCore::len)
+    }
+
+    #[must_use]
+    pub fn is_empty() -> bool {
+        RATE_LIMITER.with_borrow(RateLimiterCore::is_empty)
+    }
+
+    pub fn clear() {
+        RATE_LIMITER.with_borrow_mut(RateLimiterCore::clear);
+    }
+}
+
+///
+/// RateLimiterCore
+///
+
+#[derive(Debug, Default)]
+pub struct RateLimiterCore {
+    buckets: HashMap<RateLimitKey, (Bucket, RateLimit)>,
+    last_evict: u64,
+}
+
+impl RateLimiterCore {
+    pub fn check(&mut self, key: RateLimitKey, limit: RateLimit, now: u64) -> bool {
+        if now.saturating_sub(self.last_evict) >= EVICT_INTERVAL_MILLIS {
+            self.evict(now);
+        }
+        if self.buckets.len() >= MAX_BUCKETS && !self.buckets.contains_key(&key) {
+            self.evict(now);
+            self.evict_oldest();
+        }
+
+        let (bucket, stored) = self.buckets.entry(key).or_insert((
+            Bucket {
+                tokens: f64::from(limit.capacity),
+                updated_at: now,
+            },
+            limit,
+        ));
+        *stored = limit;
+
+        refill(bucket, limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    #[must_use]
+    pub fn peek(&self, key: &RateLimitKey, limit: RateLimit, now: u64) -> bool {
+        let Some((bucket, _)) = self.buckets.get(key) else {
+            return limit.capacity > 0;
+        };
+
+        let mut bucket = *bucket;
+        refill(&mut bucket, limit, now);
+
+        bucket.tokens >= 1.0
+    }
+
+    // evict
+    // full buckets are the same as no bucket at all
+    pub fn evict(&mut self, now: u64) {
+        self.buckets.retain(|_, (bucket, limit)| {
+            refill(bucket, *limit, now);
+
+            bucket.tokens < f64::from(limit.capacity)
+        });
+        self.last_evict = now;
+    }
+
+    // evict_oldest
+    // only when we're still at MAX_BUCKETS after a normal evict
+    fn evict_oldest(&mut self) {
+        if let Some(key) = self
+            .buckets
+            .iter()
+            .min_by_key(|(_, (bucket, _))| bucket.updated_at)
+            .map(|(key, _)| key.clone())
+        {
+            self.buckets.remove(&key);
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+}
+
+// refill
+#[allow(clippy::cast_precision_loss)]
+fn refill(bucket: &mut Bucket, limit: RateLimit, now: u64) {
+    let elapsed = now.saturating_sub(bucket.updated_at) as f64;
+    let per_token = limit.refill_secs.saturating_mul(1_000) as f64;
+
+    bucket.tokens = (bucket.tokens + elapsed / per_token).min(f64::from(limit.capacity));
+    bucket.updated_at = now;
+}
+
+///
+/// TESTS
+///
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        capacity: 2,
+        refill_secs: 10,
+    };
+
+    fn key(n: u8) -> RateLimitKey {
+        (Principal::from_slice(&[n]), None)
+    }
+
+    #[test]
+    fn bucket_empties_and_refills() {
+        let mut core = RateLimiterCore::default();
+
+        assert!(core.check(key(1), LIMIT, 0));
+        assert!(core.check(key(1), LIMIT, 0));
+        assert!(!core.check(key(1), LIMIT, 0));
+
+        // other callers have their own bucket
+        assert!(core.check(key(2), LIMIT, 0));
+
+        // one token back after refill_secs
+        assert!(core.check(key(1), LIMIT, 10_000));
+        assert!(!core.check(key(1), LIMIT, 10_000));
+    }
+
+    #[test]
+    fn methods_have_separate_buckets() {
+        let mut core = RateLimiterCore::default();
+        let pid = Principal::from_slice(&[1]);
+
+        let one = RateLimit {
+            capacity: 1,
+            refill_secs: 10,
+        };
+        assert!(core.check((pid, Some("a".to_string())), one, 0));
+        assert!(core.check((pid, Some("b".to_string())), one, 0));
+        assert!(!core.check((pid, Some("a".to_string())), one, 0));
+    }
+
+    #[test]
+    fn peek_doesnt_take_a_token() {
+        let mut core = RateLimiterCore::default();
+        let one = RateLimit {
+            capacity: 1,
+            refill_secs: 10,
+        };
+
+        assert!(core.peek(&key(1), one, 0));
+        assert!(core.peek(&key(1), one, 0));
+        assert!(core.check(key(1), one, 0));
+        assert!(!core.peek(&key(1), one, 0));
+        assert!(core.peek(&key(1), one, 10_000));
+    }
+
+    #[test]
+    fn evict_removes_full_buckets() {
+        let mut core = RateLimiterCore::default();
+
+        assert!(core.check(key(1), LIMIT, 0));
+        assert!(core.check(key(2), LIMIT, 0));
+        assert!(core.check(key(2), LIMIT, 0));
+        assert_eq!(core.len(), 2);
+
+        // key 1 is full again after 10s, key 2 isn't
+        core.evict(10_000);
+        assert_eq!(core.len(), 1);
+
+        core.evict(20_000);
+        assert!(core.is_empty());
+    }
+}
@@ -42,6 +42,24 @@ impl WasmRegistry {
         WASM_REGISTRY.with_borrow(|reg| reg.get(ty).cloned())
     }
 
+    // types
+    // every canister type there's a wasm for
+    #[must_use]
+    pub fn types() -> Vec<CanisterType> {
+        WASM_REGISTRY.with_borrow(|reg| reg.keys().cloned().collect())
+    }
+
+    // smallest
+    // the type with the smallest wasm, the cheapest thing to install
+    #[must_use]
+    pub fn smallest() -> Option<CanisterType> {
+        WASM_REGISTRY.with_borrow(|reg| {
+            reg.iter()
+                .min_by_key(|(_, wasm)| wasm.len())
+                .map(|(ty, _)| ty.clone())
+        })
+    }
+
     pub fn try_get(ty: &CanisterType) -> Result<WasmModule, Error> {
         Self::get(ty).ok_or_else(|| {
             Error::from(StateError::WasmRegistryError(
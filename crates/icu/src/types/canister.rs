@@ -11,6 +11,7 @@ use std::{borrow::Cow, str::FromStr};
 #[derive(
     CandidType, Clone, Debug, Eq, Ord, Display, PartialOrd, Deserialize, Serialize, PartialEq, Hash,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(transparent)]
 pub struct CanisterType(pub Cow<'static, str>);
 
@@ -46,6 +46,38 @@ pub fn now_nanos() -> u64 {
     time_nanos() as u64
 }
 
+/// format_utc
+/// unix seconds as "YYYY-MM-DD HH:MM:SS UTC", for messages people read
+#[must_use]
+pub fn format_utc(secs: u64) -> String {
+    let days = secs / 86_400;
+    let rem = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+// civil_from_days
+// days since 1970-01-01 to (year, month, day), Howard Hinnant's algorithm
+const fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
 ///
 /// TESTS
 ///
@@ -60,4 +92,11 @@ pub mod test {
         let current_year_secs = 1_700_000_000; // ≈ Oct 2023
         assert!(now > current_year_secs);
     }
+
+    #[test]
+    fn formats_utc() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_utc(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_utc(1_760_789_045), "2025-10-18 12:04:05 UTC");
+    }
 }